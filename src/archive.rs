@@ -0,0 +1,374 @@
+use chrono::Utc;
+use once_cell::sync::OnceCell;
+use rusqlite::{Connection, params};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info, warn};
+
+use crate::enrich::EnrichedKill;
+use crate::settings;
+
+const ARCHIVE_QUEUE_SIZE: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArchiveFormat {
+    Jsonl,
+    Sqlite,
+}
+
+#[derive(Clone, Debug)]
+pub struct ArchiveSettings {
+    pub format: ArchiveFormat,
+    pub path: PathBuf,
+    pub tracked_only: bool,
+}
+
+impl ArchiveSettings {
+    /// Reads the archive settings from the environment, returning `None` when
+    /// archiving is disabled (the default).
+    pub fn from_env() -> Option<ArchiveSettings> {
+        let format = match settings::var("DRAUGUR_ARCHIVE") {
+            Ok(value) => match value.to_lowercase().as_str() {
+                "jsonl" => ArchiveFormat::Jsonl,
+                "sqlite" => ArchiveFormat::Sqlite,
+                "" | "off" | "none" => return None,
+                other => {
+                    warn!(
+                        "unknown DRAUGUR_ARCHIVE value '{}', archiving disabled",
+                        other
+                    );
+                    return None;
+                }
+            },
+            Err(_) => return None,
+        };
+
+        let path = match settings::var("DRAUGUR_ARCHIVE_PATH") {
+            Ok(path) => settings::data_path(path),
+            Err(_) => match format {
+                ArchiveFormat::Jsonl => settings::data_path("archive"),
+                ArchiveFormat::Sqlite => settings::data_path("archive.sqlite"),
+            },
+        };
+
+        let tracked_only = settings::var("DRAUGUR_ARCHIVE_TRACKED_ONLY")
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+
+        Some(ArchiveSettings {
+            format,
+            path,
+            tracked_only,
+        })
+    }
+}
+
+enum ArchiveMessage {
+    Kill(Arc<EnrichedKill>),
+    /// Answered once everything queued before it is on disk.
+    Flush(oneshot::Sender<()>),
+}
+
+struct ArchiveState {
+    settings: ArchiveSettings,
+    tx: mpsc::Sender<ArchiveMessage>,
+}
+
+static ARCHIVE: OnceCell<ArchiveState> = OnceCell::new();
+
+/// Starts the archive writer if archiving is enabled. The writer runs on its
+/// own thread so disk and SQLite I/O never block the kill feed.
+pub fn init_archive() {
+    let Some(settings) = ArchiveSettings::from_env() else {
+        info!("killmail archive disabled");
+        return;
+    };
+
+    let mut writer: Box<dyn ArchiveWriter> = match settings.format {
+        ArchiveFormat::Jsonl => match JsonlWriter::new(settings.path.clone()) {
+            Ok(writer) => Box::new(writer),
+            Err(e) => {
+                error!("failed to open jsonl archive {:?}: {}", settings.path, e);
+                return;
+            }
+        },
+        ArchiveFormat::Sqlite => match SqliteWriter::new(&settings.path) {
+            Ok(writer) => Box::new(writer),
+            Err(e) => {
+                error!("failed to open sqlite archive {:?}: {}", settings.path, e);
+                return;
+            }
+        },
+    };
+
+    let (tx, mut rx) = mpsc::channel::<ArchiveMessage>(ARCHIVE_QUEUE_SIZE);
+
+    std::thread::spawn(move || {
+        while let Some(message) = rx.blocking_recv() {
+            match message {
+                ArchiveMessage::Kill(kill) => {
+                    if let Err(e) = writer.write(&kill) {
+                        error!(
+                            "failed to archive killmail {}: {}",
+                            kill.killmail.killmail_id, e
+                        );
+                    }
+                }
+                ArchiveMessage::Flush(done) => {
+                    if let Err(e) = writer.flush() {
+                        error!("failed to flush archive: {}", e);
+                    }
+                    let _ = done.send(());
+                }
+            }
+        }
+    });
+
+    info!(
+        "archiving {} killmails to {:?} ({:?})",
+        if settings.tracked_only {
+            "tracked"
+        } else {
+            "all"
+        },
+        settings.path,
+        settings.format
+    );
+
+    let _ = ARCHIVE.set(ArchiveState { settings, tx });
+}
+
+/// Queues a killmail for archiving. `tracked` tells whether any guild matched
+/// it, which matters when only tracked killmails are archived.
+pub fn archive_kill(kill: &Arc<EnrichedKill>, tracked: bool) {
+    let Some(state) = ARCHIVE.get() else {
+        return;
+    };
+
+    if state.settings.tracked_only && !tracked {
+        return;
+    }
+
+    if state
+        .tx
+        .try_send(ArchiveMessage::Kill(Arc::clone(kill)))
+        .is_err()
+    {
+        warn!(
+            "archive queue full, dropping killmail {}",
+            kill.killmail.killmail_id
+        );
+    }
+}
+
+/// Waits until every queued killmail is written and synced to disk, giving
+/// up after `timeout`.
+pub async fn flush_archive(timeout: Duration) {
+    let Some(state) = ARCHIVE.get() else {
+        return;
+    };
+
+    let (done_tx, done_rx) = oneshot::channel();
+    let flushed = tokio::time::timeout(timeout, async {
+        state.tx.send(ArchiveMessage::Flush(done_tx)).await.ok()?;
+        done_rx.await.ok()
+    })
+    .await;
+
+    match flushed {
+        Ok(Some(())) => info!("archive flushed"),
+        _ => warn!("archive not flushed within {:?}", timeout),
+    }
+}
+
+trait ArchiveWriter: Send {
+    fn write(&mut self, kill: &EnrichedKill) -> Result<(), Box<dyn std::error::Error>>;
+
+    fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+}
+
+/// Appends killmails to one `.jsonl` file per UTC day. Each line carries the
+/// `killmail` and `zkb` objects of a RedisQ `package` next to the resolved
+/// names, so archived files can be replayed like recorded feed traffic.
+struct JsonlWriter {
+    dir: PathBuf,
+    current_day: String,
+    file: Option<File>,
+}
+
+impl JsonlWriter {
+    fn new(dir: PathBuf) -> std::io::Result<JsonlWriter> {
+        fs::create_dir_all(&dir)?;
+        Ok(JsonlWriter {
+            dir,
+            current_day: String::new(),
+            file: None,
+        })
+    }
+}
+
+impl ArchiveWriter for JsonlWriter {
+    fn write(&mut self, kill: &EnrichedKill) -> Result<(), Box<dyn std::error::Error>> {
+        let day = Utc::now().format("%Y-%m-%d").to_string();
+        if self.file.is_none() || day != self.current_day {
+            let path = self.dir.join(format!("killmails-{}.jsonl", day));
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            self.file = Some(file);
+            self.current_day = day;
+        }
+
+        let mut line = serde_json::to_string(kill)?;
+        line.push('\n');
+
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(line.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(file) = self.file.as_mut() {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+/// Stores killmails in a SQLite table, with the entities involved in a side
+/// table so history can be queried by character, corporation or alliance.
+struct SqliteWriter {
+    conn: Connection,
+}
+
+impl SqliteWriter {
+    fn new(path: &Path) -> rusqlite::Result<SqliteWriter> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS killmails (
+                killmail_id INTEGER PRIMARY KEY,
+                killmail_time TEXT NOT NULL,
+                solar_system_id INTEGER NOT NULL,
+                victim_character_id INTEGER NOT NULL,
+                victim_corporation_id INTEGER NOT NULL,
+                victim_alliance_id INTEGER,
+                victim_ship_type_id INTEGER NOT NULL,
+                hash TEXT NOT NULL,
+                location_id INTEGER NOT NULL,
+                fitted_value REAL NOT NULL,
+                dropped_value REAL NOT NULL,
+                destroyed_value REAL NOT NULL,
+                total_value REAL NOT NULL,
+                points REAL NOT NULL,
+                npc INTEGER NOT NULL,
+                solo INTEGER NOT NULL,
+                awox INTEGER NOT NULL,
+                attacker_count INTEGER NOT NULL,
+                victim_name TEXT NOT NULL,
+                victim_ship_name TEXT NOT NULL,
+                victim_corp_name TEXT NOT NULL,
+                system_name TEXT NOT NULL,
+                region_id INTEGER NOT NULL,
+                region_name TEXT NOT NULL,
+                killmail TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_killmails_time ON killmails (killmail_time);
+            CREATE INDEX IF NOT EXISTS idx_killmails_system ON killmails (solar_system_id);
+            CREATE TABLE IF NOT EXISTS killmail_entities (
+                killmail_id INTEGER NOT NULL,
+                entity_id INTEGER NOT NULL,
+                role TEXT NOT NULL,
+                PRIMARY KEY (killmail_id, entity_id, role)
+            );
+            CREATE INDEX IF NOT EXISTS idx_killmail_entities_entity
+                ON killmail_entities (entity_id);",
+        )?;
+        Ok(SqliteWriter { conn })
+    }
+}
+
+impl ArchiveWriter for SqliteWriter {
+    fn write(&mut self, kill: &EnrichedKill) -> Result<(), Box<dyn std::error::Error>> {
+        let km = &kill.killmail;
+        let zkb = &kill.zkb;
+        let raw = serde_json::to_string(km)?;
+
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO killmails (
+                killmail_id, killmail_time, solar_system_id,
+                victim_character_id, victim_corporation_id, victim_alliance_id,
+                victim_ship_type_id, hash, location_id, fitted_value, dropped_value,
+                destroyed_value, total_value, points, npc, solo, awox,
+                attacker_count, victim_name, victim_ship_name, victim_corp_name,
+                system_name, region_id, region_name, killmail
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                      ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25)",
+            params![
+                km.killmail_id,
+                km.killmail_time,
+                km.solar_system_id,
+                km.victim.character_id,
+                km.victim.corporation_id,
+                km.victim.alliance_id,
+                km.victim.ship_type_id,
+                zkb.hash,
+                zkb.location_id,
+                zkb.fitted_value,
+                zkb.dropped_value,
+                zkb.destroyed_value,
+                zkb.total_value,
+                zkb.points,
+                zkb.npc,
+                zkb.solo,
+                zkb.awox,
+                km.attackers.len() as i64,
+                kill.victim_name,
+                kill.victim_ship_name,
+                kill.victim_corp_name,
+                kill.system_name,
+                kill.region_id,
+                kill.region_name,
+                raw,
+            ],
+        )?;
+
+        {
+            let mut insert = tx.prepare(
+                "INSERT OR IGNORE INTO killmail_entities (killmail_id, entity_id, role)
+                 VALUES (?1, ?2, ?3)",
+            )?;
+
+            let victim_ids = [
+                km.victim.character_id,
+                km.victim.corporation_id,
+                km.victim.alliance_id.unwrap_or(0),
+                km.victim.ship_type_id,
+            ];
+            for id in victim_ids.into_iter().filter(|id| *id != 0) {
+                insert.execute(params![km.killmail_id, id, "victim"])?;
+            }
+
+            for attacker in &km.attackers {
+                let attacker_ids = [
+                    attacker.character_id,
+                    attacker.corporation_id,
+                    attacker.alliance_id.unwrap_or(0),
+                    attacker.ship_type_id,
+                ];
+                for id in attacker_ids.into_iter().filter(|id| *id != 0) {
+                    insert.execute(params![km.killmail_id, id, "attacker"])?;
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+use once_cell::sync::Lazy;
+use serenity::all::{CreateMessage, GuildId, Http};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::configs::{lock_configs, save_config, save_configs};
+use crate::ws::SERVER_CONFIGS;
+
+/// Consecutive 403/404 responses after which a channel is disabled.
+const MAX_CHANNEL_FAILURES: u32 = 3;
+
+static CHANNEL_FAILURES: Lazy<Mutex<HashMap<u64, u32>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Drops the configuration of a guild the bot was removed from.
+pub async fn guild_removed(guild_id: u64) {
+    let lock = lock_configs().await;
+    let removed = SERVER_CONFIGS.write().await.remove(&guild_id).is_some();
+    if removed {
+        info!("removed configuration for departed guild {}", guild_id);
+        // Failures are logged by `save_config`, there's nobody else to tell.
+        let _ = save_config(&lock, guild_id).await;
+    }
+}
+
+/// Disables every configuration posting to a channel that was deleted.
+pub async fn channel_deleted(http: &Http, channel_id: u64) {
+    disable_channel(http, channel_id, "the configured channel was deleted").await;
+}
+
+/// Resets the failure count of a channel after a successful post.
+pub fn channel_succeeded(channel_id: u64) {
+    if let Ok(mut failures) = CHANNEL_FAILURES.lock() {
+        failures.remove(&channel_id);
+    }
+}
+
+/// Records a 403/404 from Discord for `channel_id`, disabling the channel once
+/// it keeps failing.
+pub async fn channel_failed(http: &Http, channel_id: u64) {
+    let failures = match CHANNEL_FAILURES.lock() {
+        Ok(mut failures) => {
+            let count = failures.entry(channel_id).or_insert(0);
+            *count += 1;
+            *count
+        }
+        Err(_) => return,
+    };
+
+    if failures >= MAX_CHANNEL_FAILURES {
+        channel_succeeded(channel_id);
+        disable_channel(
+            http,
+            channel_id,
+            "the bot can no longer post in the configured channel",
+        )
+        .await;
+    }
+}
+
+async fn disable_channel(http: &Http, channel_id: u64, reason: &str) {
+    let lock = lock_configs().await;
+    let disabled: Vec<u64> = {
+        let mut configs = SERVER_CONFIGS.write().await;
+        configs
+            .iter_mut()
+            .filter(|(_, config)| config.channel_id == channel_id && config.disabled.is_none())
+            .map(|(guild_id, config)| {
+                config.disabled = Some(reason.to_string());
+                *guild_id
+            })
+            .collect()
+    };
+
+    if !disabled.is_empty() {
+        let _ = save_configs(&lock, &disabled).await;
+    }
+    drop(lock);
+
+    for guild_id in disabled {
+        warn!(
+            "disabled kill feed for guild {} in channel {}: {}",
+            guild_id, channel_id, reason
+        );
+        notify_owner(http, guild_id, reason).await;
+    }
+}
+
+/// Lets the guild owner know their kill feed stopped, if they accept DMs.
+async fn notify_owner(http: &Http, guild_id: u64, reason: &str) {
+    let guild = match GuildId::new(guild_id).to_partial_guild(http).await {
+        Ok(guild) => guild,
+        Err(e) => {
+            warn!("cannot look up owner of guild {}: {}", guild_id, e);
+            return;
+        }
+    };
+
+    let content = format!(
+        "Draugur stopped posting killmails in **{}** because {}. Run `/setup` in the channel you want killmails in to turn it back on.",
+        guild.name, reason
+    );
+
+    let dm = match guild.owner_id.create_dm_channel(http).await {
+        Ok(dm) => dm,
+        Err(e) => {
+            warn!("cannot open DM with owner of guild {}: {}", guild_id, e);
+            return;
+        }
+    };
+
+    if let Err(e) = dm
+        .id
+        .send_message(http, CreateMessage::new().content(content))
+        .await
+    {
+        warn!("cannot notify owner of guild {}: {}", guild_id, e);
+    }
+}
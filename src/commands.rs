@@ -1,11 +1,23 @@
-use log::{error, info, warn};
+use futures::future::join_all;
 use serenity::all::{
-    CommandInteraction, CreateInteractionResponse, CreateInteractionResponseMessage,
+    ButtonStyle, ChannelId, CommandDataOption, CommandDataOptionValue, CommandInteraction,
+    ComponentInteraction, CreateActionRow, CreateAttachment, CreateButton,
+    CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+    EditInteractionResponse, Member, Permissions, RoleId, UserId,
 };
 use serenity::client::Context;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tracing::{error, info, warn};
 
-use crate::configs::save_configs;
-use crate::ws::{SERVER_CONFIGS, ServerConfig};
+use crate::configs::{lock_configs, reload_configs, save_config};
+use crate::enrich::EnrichedKill;
+use crate::esi::Name;
+use crate::helpers::get_latest_recent_kill;
+use crate::models::Killmail;
+use crate::msg::create_embed;
+use crate::ws::{SERVER_CONFIGS, ServerConfig, find_match};
+use crate::zkill::{fetch_kill, parse_kill_id};
 
 pub async fn setup_command(ctx: &Context, command: &CommandInteraction) {
     let guild_id = match command.guild_id {
@@ -16,6 +28,11 @@ pub async fn setup_command(ctx: &Context, command: &CommandInteraction) {
         }
     };
 
+    if !can_configure(command, guild_id).await {
+        send_error_response(ctx, command, NOT_ALLOWED).await;
+        return;
+    }
+
     let follow_id_str = match command
         .data
         .options
@@ -40,17 +57,31 @@ pub async fn setup_command(ctx: &Context, command: &CommandInteraction) {
 
     let channel_id = command.channel_id.get();
 
+    let missing = missing_permissions(ctx, command, channel_id);
+    if !missing.is_empty() {
+        send_error_response(
+            ctx,
+            command,
+            &missing_permissions_message(channel_id, missing, "/setup"),
+        )
+        .await;
+        return;
+    }
+
     info!(
         "setting up: guild_id={}, follow_id={}, channel_id={}",
         guild_id, follow_id, channel_id
     );
 
+    let lock = lock_configs().await;
     {
         let mut configs = SERVER_CONFIGS.write().await;
 
         let config = configs.entry(guild_id).or_insert(ServerConfig {
             follow_ids: Vec::new(),
             channel_id,
+            disabled: None,
+            admin_role_id: None,
         });
 
         if !config.follow_ids.contains(&follow_id) {
@@ -58,19 +89,112 @@ pub async fn setup_command(ctx: &Context, command: &CommandInteraction) {
         }
 
         config.channel_id = channel_id;
+        config.disabled = None;
+    }
+    let saved = save_config(&lock, guild_id).await;
+    drop(lock);
+    if let Err(e) = saved {
+        send_error_response(ctx, command, &not_saved(&e)).await;
+        return;
     }
-    save_configs().await;
 
-    let response =
-        CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().content(
-            format!("✅ Draugur configured! Now tracking ID: {}", follow_id),
-        ));
+    let test_button = CreateButton::new(TEST_EMBED_BUTTON)
+        .label("Send test embed")
+        .style(ButtonStyle::Secondary);
+
+    let response = CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(format!(
+                "✅ Draugur configured! Now tracking ID: {}",
+                follow_id
+            ))
+            .components(vec![CreateActionRow::Buttons(vec![test_button])]),
+    );
 
     if let Err(why) = command.create_response(&ctx.http, response).await {
         error!("Cannot repond to slash command: {}", why);
     }
 }
 
+/// Custom ID of the "Send test embed" button offered after `/setup`.
+pub const TEST_EMBED_BUTTON: &str = "setup_test_embed";
+
+/// Permissions the bot needs in a channel to post killmails.
+const REQUIRED_PERMISSIONS: Permissions = Permissions::VIEW_CHANNEL
+    .union(Permissions::SEND_MESSAGES)
+    .union(Permissions::EMBED_LINKS);
+
+/// Returns the permissions the bot lacks in `channel_id`. The bot's
+/// effective permissions come from the cache, falling back to the ones
+/// Discord attaches to the interaction when it's the command's own channel.
+fn missing_permissions(
+    ctx: &Context,
+    command: &CommandInteraction,
+    channel_id: u64,
+) -> Permissions {
+    let channel_id = ChannelId::new(channel_id);
+    let cached = command.guild_id.and_then(|guild_id| {
+        let guild = ctx.cache.guild(guild_id)?;
+        let channel = guild.channels.get(&channel_id)?;
+        let member = guild.members.get(&ctx.cache.current_user().id)?;
+        Some(guild.user_permissions_in(channel, member))
+    });
+    let attached = command
+        .app_permissions
+        .filter(|_| channel_id == command.channel_id);
+
+    match cached.or(attached) {
+        Some(permissions) => REQUIRED_PERMISSIONS - permissions,
+        None => Permissions::empty(),
+    }
+}
+
+/// Explains which permissions the bot is missing in `channel_id`.
+fn missing_permissions_message(channel_id: u64, missing: Permissions, retry: &str) -> String {
+    format!(
+        "I can't post killmails in <#{}>, I'm missing these permissions here: {}. \
+         Grant them to my role or pick another channel, then run `{}` again.",
+        channel_id,
+        missing.get_permission_names().join(", "),
+        retry
+    )
+}
+
+/// Posts a sample killmail embed in the channel the button was clicked in.
+/// The button sits on a public reply, so only members who may change the
+/// tracking can use it.
+pub async fn test_embed_button(ctx: &Context, component: &ComponentInteraction) {
+    let allowed = match component.guild_id {
+        Some(guild_id) => member_can_configure(component.member.as_ref(), guild_id.get()).await,
+        None => false,
+    };
+
+    let content = if allowed {
+        let embed = create_embed(&EnrichedKill::sample(), "kill");
+        let sent = component
+            .channel_id
+            .send_message(&ctx.http, CreateMessage::new().embed(embed))
+            .await;
+
+        match sent {
+            Ok(_) => "✅ Test embed sent, killmails will look like this.".to_string(),
+            Err(why) => format!("❌ I couldn't post in this channel: {}", why),
+        }
+    } else {
+        format!("❌ {}", NOT_ALLOWED)
+    };
+
+    let response = CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(content)
+            .ephemeral(true),
+    );
+
+    if let Err(why) = component.create_response(&ctx.http, response).await {
+        warn!("Cannot respond to button: {}", why);
+    }
+}
+
 fn validate_follow_id(input: &str) -> Result<i64, String> {
     if input.is_empty() {
         return Err("Follow ID cannot be empty".to_string());
@@ -107,6 +231,11 @@ pub async fn remove_command(ctx: &Context, command: &CommandInteraction) {
         }
     };
 
+    if !can_configure(command, guild_id).await {
+        send_error_response(ctx, command, NOT_ALLOWED).await;
+        return;
+    }
+
     let follow_id_str = match command
         .data
         .options
@@ -129,13 +258,14 @@ pub async fn remove_command(ctx: &Context, command: &CommandInteraction) {
         }
     };
 
+    let lock = lock_configs().await;
     let removed = {
         let mut configs = SERVER_CONFIGS.write().await;
         if let Some(config) = configs.get_mut(&guild_id) {
             if let Some(pos) = config.follow_ids.iter().position(|&x| x == follow_id) {
                 config.follow_ids.remove(pos);
 
-                if config.follow_ids.is_empty() {
+                if config.follow_ids.is_empty() && config.admin_role_id.is_none() {
                     configs.remove(&guild_id);
                 }
                 true
@@ -148,7 +278,12 @@ pub async fn remove_command(ctx: &Context, command: &CommandInteraction) {
     };
 
     if removed {
-        save_configs().await;
+        let saved = save_config(&lock, guild_id).await;
+        drop(lock);
+        if let Err(e) = saved {
+            send_error_response(ctx, command, &not_saved(&e)).await;
+            return;
+        }
         let response = CreateInteractionResponse::Message(
             CreateInteractionResponseMessage::new()
                 .content(format!("Removed ID {} from tracking list", follow_id))
@@ -189,11 +324,19 @@ pub async fn list_command(ctx: &Context, command: &CommandInteraction) {
                 .collect::<Vec<_>>()
                 .join("\n");
 
+            let status = match &config.disabled {
+                Some(reason) => format!(
+                    "⚠️ *Posting to <#{}> is disabled: {}. Run `/setup` to turn it back on.*",
+                    config.channel_id, reason
+                ),
+                None => format!("*Posting to <#{}>*", config.channel_id),
+            };
+
             format!(
-                "**Currently tracking {} ID(s):**\n{}\n\n*Posting to <#{}>*",
+                "**Currently tracking {} ID(s):**\n{}\n\n{}",
                 config.follow_ids.len(),
                 ids_list,
-                config.channel_id
+                status
             )
         }
     } else {
@@ -207,10 +350,663 @@ pub async fn list_command(ctx: &Context, command: &CommandInteraction) {
     );
 
     if let Err(why) = command.create_response(&ctx.http, response).await {
-        println!("Cannot respond to slash command: {}", why);
+        error!("cannot respond to slash command: {}", why);
+    }
+}
+
+pub async fn test_command(ctx: &Context, command: &CommandInteraction) {
+    let guild_id = match command.guild_id {
+        Some(id) => id.get(),
+        None => {
+            send_error_response(ctx, command, "This command can only be used in a server").await;
+            return;
+        }
+    };
+
+    if !can_configure(command, guild_id).await {
+        send_error_response(ctx, command, NOT_ALLOWED).await;
+        return;
+    }
+
+    let config = SERVER_CONFIGS.read().await.get(&guild_id).cloned();
+    let Some(config) = config else {
+        send_error_response(
+            ctx,
+            command,
+            "Run `/setup` first to pick a channel and a follow ID",
+        )
+        .await;
+        return;
+    };
+
+    let (kill, is_sample) = match get_latest_recent_kill().await {
+        Some(kill) => (kill, false),
+        None => (Arc::new(EnrichedKill::sample()), true),
+    };
+
+    let found = find_match(&kill.killmail, &config.follow_ids);
+    let kill_type = found
+        .as_ref()
+        .map_or("kill", |found| found.kill_type.as_str());
+
+    let sent = ChannelId::new(config.channel_id)
+        .send_message(
+            &ctx.http,
+            CreateMessage::new().embed(create_embed(&kill, kill_type)),
+        )
+        .await;
+
+    let mut lines = Vec::new();
+    match sent {
+        Ok(_) => lines.push(format!("✅ Test post sent to <#{}>.", config.channel_id)),
+        Err(why) => lines.push(format!(
+            "❌ I couldn't post in <#{}>: {}",
+            config.channel_id, why
+        )),
+    }
+
+    if is_sample {
+        lines.push(
+            "No killmails arrived in the last ten minutes, so this was a sample kill.".to_string(),
+        );
+    } else {
+        lines.push(format!(
+            "This was the latest killmail, [{}](https://zkillboard.com/kill/{}/).",
+            kill.killmail.killmail_id, kill.killmail.killmail_id
+        ));
+        lines.push(match &found {
+            Some(found) => format!(
+                "It **would have been posted** as a {}: the {} matches follow ID `{}`.",
+                found.kill_type, found.field, found.follow_id
+            ),
+            None => "It **would not have been posted**: none of your follow IDs appear as the victim, an attacker, the system or a ship type.".to_string(),
+        });
+    }
+
+    if let Some(reason) = &config.disabled {
+        lines.push(format!(
+            "⚠️ Posting is currently disabled: {}. Run `/setup` to turn it back on.",
+            reason
+        ));
+    }
+
+    let response = CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(lines.join("\n"))
+            .ephemeral(true),
+    );
+
+    if let Err(why) = command.create_response(&ctx.http, response).await {
+        warn!("Cannot respond to slash command: {}", why);
+    }
+}
+
+pub async fn explain_command(ctx: &Context, command: &CommandInteraction) {
+    let guild_id = match command.guild_id {
+        Some(id) => id.get(),
+        None => {
+            send_error_response(ctx, command, "This command can only be used in a server").await;
+            return;
+        }
+    };
+
+    let input = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "killmail")
+        .and_then(|opt| opt.value.as_str())
+        .unwrap_or_default();
+    let Some(killmail_id) = parse_kill_id(input) else {
+        send_error_response(ctx, command, "Give a killmail ID or a zKillboard kill link").await;
+        return;
+    };
+
+    let config = SERVER_CONFIGS.read().await.get(&guild_id).cloned();
+    let Some(config) = config else {
+        send_error_response(ctx, command, "This server isn't tracking anything yet").await;
+        return;
+    };
+
+    // Looking the killmail up can take a few seconds.
+    if let Err(why) = command.defer_ephemeral(&ctx.http).await {
+        warn!("Cannot defer slash command: {}", why);
+        return;
+    }
+
+    let content = match fetch_kill(killmail_id).await {
+        Ok((km, _)) => explain_match(&km, &config),
+        Err(e) => format!("❌ Cannot look up killmail {}: {}", killmail_id, e),
+    };
+
+    let response = EditInteractionResponse::new().content(content);
+    if let Err(why) = command.edit_response(&ctx.http, response).await {
+        warn!("Cannot respond to slash command: {}", why);
+    }
+}
+
+pub async fn kill_command(ctx: &Context, command: &CommandInteraction) {
+    let input = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "killmail")
+        .and_then(|opt| opt.value.as_str())
+        .unwrap_or_default();
+    let Some(killmail_id) = parse_kill_id(input) else {
+        send_error_response(ctx, command, "Give a killmail ID or a zKillboard kill link").await;
+        return;
+    };
+
+    if let Err(why) = command.defer(&ctx.http).await {
+        warn!("Cannot defer slash command: {}", why);
+        return;
+    }
+
+    let response = match fetch_kill(killmail_id).await {
+        Ok((km, zkb)) => {
+            // Show it as a loss if the victim is one of this server's follows.
+            let follow_ids = match command.guild_id {
+                Some(guild_id) => SERVER_CONFIGS
+                    .read()
+                    .await
+                    .get(&guild_id.get())
+                    .map(|config| config.follow_ids.clone())
+                    .unwrap_or_default(),
+                None => Vec::new(),
+            };
+            let kill_type = find_match(&km, &follow_ids)
+                .map_or_else(|| "kill".to_string(), |found| found.kill_type);
+
+            let kill = EnrichedKill::new(km, zkb).await;
+            EditInteractionResponse::new().embed(create_embed(&kill, &kill_type))
+        }
+        Err(e) => EditInteractionResponse::new()
+            .content(format!("❌ Cannot look up killmail {}: {}", killmail_id, e)),
+    };
+
+    if let Err(why) = command.edit_response(&ctx.http, response).await {
+        warn!("Cannot respond to slash command: {}", why);
+    }
+}
+
+/// Walks a killmail through a guild's filters the same way the kill feed
+/// does, one line per rule.
+fn explain_match(km: &Killmail, config: &ServerConfig) -> String {
+    let mut lines = vec![format!(
+        "Killmail [{}](https://zkillboard.com/kill/{}/):",
+        km.killmail_id, km.killmail_id
+    )];
+
+    for follow_id in &config.follow_ids {
+        lines.push(match find_match(km, &[*follow_id]) {
+            Some(found) => format!(
+                "✅ `{}` matches the {} ({})",
+                follow_id, found.field, found.kill_type
+            ),
+            None => format!("➖ `{}` appears nowhere on this killmail", follow_id),
+        });
+    }
+
+    let found = find_match(km, &config.follow_ids);
+    if let Some(reason) = &config.disabled {
+        lines.push(format!(
+            "❌ Posting is disabled for this server: {}. Run `/setup` to turn it back on.",
+            reason
+        ));
+    } else if let Some(found) = &found {
+        lines.push(format!(
+            "**Posted to <#{}> as a {}**, because of follow ID `{}` on the {}.",
+            config.channel_id, found.kill_type, found.follow_id, found.field
+        ));
+    }
+
+    if found.is_none() {
+        lines.push(
+            "**Not posted**: none of your follow IDs appear as the victim, an attacker, the system or a ship type."
+                .to_string(),
+        );
+    }
+
+    lines.join("\n")
+}
+
+pub async fn config_command(ctx: &Context, command: &CommandInteraction) {
+    let guild_id = match command.guild_id {
+        Some(id) => id.get(),
+        None => {
+            send_error_response(ctx, command, "This command can only be used in a server").await;
+            return;
+        }
+    };
+
+    let Some(subcommand) = command.data.options.first() else {
+        return;
+    };
+    let CommandDataOptionValue::SubCommand(options) = &subcommand.value else {
+        return;
+    };
+
+    match subcommand.name.as_str() {
+        "show" => config_show(ctx, command, guild_id).await,
+        "export" => config_export(ctx, command, guild_id, options).await,
+        "import" => config_import(ctx, command, guild_id, options).await,
+        _ => {}
+    }
+}
+
+async fn config_show(ctx: &Context, command: &CommandInteraction, guild_id: u64) {
+    let config = SERVER_CONFIGS.read().await.get(&guild_id).cloned();
+    let Some(config) = config else {
+        send_error_response(ctx, command, "This server isn't tracking anything yet").await;
+        return;
+    };
+
+    // Looking up names can take a moment.
+    if let Err(why) = command.defer_ephemeral(&ctx.http).await {
+        warn!("Cannot defer slash command: {}", why);
+        return;
+    }
+
+    let names = resolve_names(&config.follow_ids).await;
+    let mut lines = vec![format!("**Follows ({}):**", config.follow_ids.len())];
+    for follow_id in &config.follow_ids {
+        lines.push(match names.get(follow_id) {
+            Some(name) => format!(
+                "• `{}` {} ({})",
+                follow_id,
+                name.name,
+                name.category.replace('_', " ")
+            ),
+            None => format!("• `{}` (unknown to ESI)", follow_id),
+        });
+    }
+
+    lines.push(String::new());
+    lines.push(format!("**Channel:** <#{}>", config.channel_id));
+    lines.push(match &config.disabled {
+        Some(reason) => format!("**Status:** ⚠️ disabled, {}", reason),
+        None => "**Status:** posting".to_string(),
+    });
+    lines.push(match config.admin_role_id {
+        Some(role_id) => format!("**Bot admin role:** <@&{}>", role_id),
+        None => "**Bot admin role:** none".to_string(),
+    });
+
+    let response = EditInteractionResponse::new().content(lines.join("\n"));
+    if let Err(why) = command.edit_response(&ctx.http, response).await {
+        warn!("Cannot respond to slash command: {}", why);
+    }
+}
+
+/// Looks up names for `ids`, falling back to one request per ID when a
+/// single unknown ID makes ESI reject the batch.
+async fn resolve_names(ids: &[i64]) -> HashMap<i64, Name> {
+    let names = match Name::get_names(ids).await {
+        Ok(names) => names,
+        Err(_) => {
+            let lookups = ids
+                .iter()
+                .map(|id| Name::get_names(std::slice::from_ref(id)));
+            join_all(lookups)
+                .await
+                .into_iter()
+                .filter_map(Result::ok)
+                .flatten()
+                .collect()
+        }
+    };
+
+    names.into_iter().map(|name| (name.id, name)).collect()
+}
+
+async fn config_export(
+    ctx: &Context,
+    command: &CommandInteraction,
+    guild_id: u64,
+    options: &[CommandDataOption],
+) {
+    if !can_configure(command, guild_id).await {
+        send_error_response(ctx, command, NOT_ALLOWED).await;
+        return;
+    }
+
+    let config = SERVER_CONFIGS.read().await.get(&guild_id).cloned();
+    let Some(config) = config else {
+        send_error_response(ctx, command, "This server isn't tracking anything yet").await;
+        return;
+    };
+
+    let format = options
+        .iter()
+        .find(|opt| opt.name == "format")
+        .and_then(|opt| opt.value.as_str())
+        .unwrap_or("toml");
+
+    let exported = match format {
+        "json" => serde_json::to_string_pretty(&config).map_err(|e| e.to_string()),
+        _ => toml::to_string(&config).map_err(|e| e.to_string()),
+    };
+    let exported = match exported {
+        Ok(exported) => exported,
+        Err(e) => {
+            error!("cannot export config for guild {}: {}", guild_id, e);
+            send_error_response(ctx, command, "Exporting the configuration failed").await;
+            return;
+        }
+    };
+
+    let file_name = format!("draugur-{}.{}", guild_id, format);
+    let response = CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(
+                "Here is this server's configuration. Use `/config import` to load it elsewhere.",
+            )
+            .add_file(CreateAttachment::bytes(exported.into_bytes(), file_name))
+            .ephemeral(true),
+    );
+
+    if let Err(why) = command.create_response(&ctx.http, response).await {
+        warn!("Cannot respond to slash command: {}", why);
+    }
+}
+
+async fn config_import(
+    ctx: &Context,
+    command: &CommandInteraction,
+    guild_id: u64,
+    options: &[CommandDataOption],
+) {
+    if !can_configure(command, guild_id).await {
+        send_error_response(ctx, command, NOT_ALLOWED).await;
+        return;
+    }
+
+    let attachment = options
+        .iter()
+        .find(|opt| opt.name == "file")
+        .and_then(|opt| opt.value.as_attachment_id())
+        .and_then(|id| command.data.resolved.attachments.get(&id));
+    let Some(attachment) = attachment else {
+        send_error_response(ctx, command, "Attach a file exported with `/config export`").await;
+        return;
+    };
+
+    if attachment.size > MAX_IMPORT_SIZE {
+        send_error_response(ctx, command, "That file is too large to be a configuration").await;
+        return;
+    }
+
+    let imported = match attachment.download().await {
+        Ok(bytes) => parse_imported_config(&attachment.filename, &bytes),
+        Err(e) => Err(format!("cannot download the file: {}", e)),
+    };
+    let imported = match imported {
+        Ok(imported) => imported,
+        Err(e) => {
+            send_error_response(ctx, command, &format!("Cannot import that file: {}", e)).await;
+            return;
+        }
+    };
+
+    // Channels and roles only carry over when they exist in this server, so
+    // a config cloned from another server posts where the import was run.
+    let (channel_in_guild, role_in_guild) = match ctx.cache.guild(guild_id) {
+        Some(guild) => (
+            guild
+                .channels
+                .contains_key(&ChannelId::new(imported.channel_id)),
+            imported
+                .admin_role_id
+                .is_some_and(|role_id| guild.roles.contains_key(&RoleId::new(role_id))),
+        ),
+        None => (false, false),
+    };
+    let channel_id = if channel_in_guild {
+        imported.channel_id
+    } else {
+        command.channel_id.get()
+    };
+
+    let missing = missing_permissions(ctx, command, channel_id);
+    if !missing.is_empty() {
+        send_error_response(
+            ctx,
+            command,
+            &missing_permissions_message(channel_id, missing, "/config import"),
+        )
+        .await;
+        return;
+    }
+
+    let follows = imported.follow_ids.len();
+    let lock = lock_configs().await;
+    {
+        let mut configs = SERVER_CONFIGS.write().await;
+        let admin_role_id = if role_in_guild {
+            imported.admin_role_id
+        } else {
+            configs
+                .get(&guild_id)
+                .and_then(|config| config.admin_role_id)
+        };
+        configs.insert(
+            guild_id,
+            ServerConfig {
+                follow_ids: imported.follow_ids,
+                channel_id,
+                disabled: None,
+                admin_role_id,
+            },
+        );
+    }
+    let saved = save_config(&lock, guild_id).await;
+    drop(lock);
+    if let Err(e) = saved {
+        send_error_response(ctx, command, &not_saved(&e)).await;
+        return;
+    }
+    info!("imported {} follow(s) for guild {}", follows, guild_id);
+
+    let response = CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(format!(
+                "✅ Imported {} follow ID(s), posting to <#{}>",
+                follows, channel_id
+            ))
+            .ephemeral(true),
+    );
+
+    if let Err(why) = command.create_response(&ctx.http, response).await {
+        warn!("Cannot respond to slash command: {}", why);
     }
 }
 
+/// Exported configurations are a few hundred bytes, anything much larger
+/// isn't one.
+const MAX_IMPORT_SIZE: u32 = 64 * 1024;
+
+/// Parses an exported configuration as JSON or TOML, going by the file
+/// extension, and checks every follow ID the way `/setup` does.
+fn parse_imported_config(file_name: &str, bytes: &[u8]) -> Result<ServerConfig, String> {
+    let text = std::str::from_utf8(bytes).map_err(|_| "the file isn't text".to_string())?;
+    let mut config: ServerConfig = if file_name.ends_with(".json") {
+        serde_json::from_str(text).map_err(|e| e.to_string())?
+    } else {
+        toml::from_str(text).map_err(|e| e.to_string())?
+    };
+
+    for follow_id in &config.follow_ids {
+        validate_follow_id(&follow_id.to_string())
+            .map_err(|e| format!("{} (`{}`)", e, follow_id))?;
+    }
+    let mut seen = HashSet::new();
+    config.follow_ids.retain(|id| seen.insert(*id));
+
+    Ok(config)
+}
+
+pub async fn admin_command(ctx: &Context, command: &CommandInteraction) {
+    if !is_bot_owner(ctx, command.user.id).await {
+        send_error_response(ctx, command, "Only the owner of this bot can use `/admin`").await;
+        return;
+    }
+
+    let Some(subcommand) = command.data.options.first() else {
+        return;
+    };
+
+    let content = match subcommand.name.as_str() {
+        "reload" => match reload_configs().await {
+            Ok(count) => format!("✅ Reloaded {} server configuration(s)", count),
+            Err(e) => format!(
+                "❌ Reload failed, the running configuration is unchanged: {}",
+                e
+            ),
+        },
+        _ => return,
+    };
+
+    let response = CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(content)
+            .ephemeral(true),
+    );
+
+    if let Err(why) = command.create_response(&ctx.http, response).await {
+        warn!("Cannot respond to slash command: {}", why);
+    }
+}
+
+/// Whether `user_id` owns the bot's Discord application, or is on the team
+/// that does.
+async fn is_bot_owner(ctx: &Context, user_id: UserId) -> bool {
+    match ctx.http.get_current_application_info().await {
+        Ok(info) => {
+            info.owner.is_some_and(|owner| owner.id == user_id)
+                || info
+                    .team
+                    .is_some_and(|team| team.members.iter().any(|member| member.user.id == user_id))
+        }
+        Err(e) => {
+            warn!("cannot look up the bot's owner: {}", e);
+            false
+        }
+    }
+}
+
+const NOT_ALLOWED: &str =
+    "You need the Manage Server permission or this server's bot admin role to change tracking";
+
+/// Whether the member who ran `command` may change the guild's tracking:
+/// anyone with Manage Server, or members of the guild's bot admin role.
+async fn can_configure(command: &CommandInteraction, guild_id: u64) -> bool {
+    member_can_configure(command.member.as_deref(), guild_id).await
+}
+
+/// `can_configure` for any interaction, given the member who triggered it.
+async fn member_can_configure(member: Option<&Member>, guild_id: u64) -> bool {
+    let Some(member) = member else {
+        return false;
+    };
+
+    if member
+        .permissions
+        .is_some_and(|permissions| permissions.manage_guild() || permissions.administrator())
+    {
+        return true;
+    }
+
+    let configs = SERVER_CONFIGS.read().await;
+    configs
+        .get(&guild_id)
+        .and_then(|config| config.admin_role_id)
+        .is_some_and(|role_id| member.roles.iter().any(|role| role.get() == role_id))
+}
+
+pub async fn adminrole_command(ctx: &Context, command: &CommandInteraction) {
+    let guild_id = match command.guild_id {
+        Some(id) => id.get(),
+        None => {
+            send_error_response(ctx, command, "This command can only be used in a server").await;
+            return;
+        }
+    };
+
+    // Only real managers may hand out access, the admin role itself can't.
+    let is_manager = command.member.as_ref().is_some_and(|member| {
+        member
+            .permissions
+            .is_some_and(|permissions| permissions.manage_guild() || permissions.administrator())
+    });
+    if !is_manager {
+        send_error_response(
+            ctx,
+            command,
+            "You need the Manage Server permission to set the bot admin role",
+        )
+        .await;
+        return;
+    }
+
+    let role_id = command
+        .data
+        .options
+        .iter()
+        .find(|opt| opt.name == "role")
+        .and_then(|opt| opt.value.as_role_id());
+
+    let lock = lock_configs().await;
+    let updated = {
+        let mut configs = SERVER_CONFIGS.write().await;
+        match configs.get_mut(&guild_id) {
+            Some(config) => {
+                config.admin_role_id = role_id.map(|role| role.get());
+                true
+            }
+            None => false,
+        }
+    };
+
+    if !updated {
+        send_error_response(ctx, command, "Run `/setup` before setting a bot admin role").await;
+        return;
+    }
+    let saved = save_config(&lock, guild_id).await;
+    drop(lock);
+    if let Err(e) = saved {
+        send_error_response(ctx, command, &not_saved(&e)).await;
+        return;
+    }
+
+    let content = match role_id {
+        Some(role) => format!(
+            "✅ Members of <@&{}> can now use `/setup` and `/remove`",
+            role
+        ),
+        None => "✅ Bot admin role cleared, only members with Manage Server can change tracking"
+            .to_string(),
+    };
+
+    let response = CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(content)
+            .ephemeral(true),
+    );
+
+    if let Err(why) = command.create_response(&ctx.http, response).await {
+        warn!("Cannot respond to slash command: {}", why);
+    }
+}
+
+/// Error shown when a change took effect but couldn't be stored.
+fn not_saved(reason: &str) -> String {
+    format!(
+        "The change is active, but saving it failed and it will be lost on restart: {}",
+        reason
+    )
+}
+
 async fn send_error_response(ctx: &Context, command: &CommandInteraction, message: &str) {
     let response = CreateInteractionResponse::Message(
         CreateInteractionResponseMessage::new()
@@ -1,46 +1,1070 @@
-use log::{error, info};
+use chrono::Utc;
+use once_cell::sync::OnceCell;
+use rusqlite::{Connection, params};
+use serde::Serialize;
 use std::collections::HashMap;
-use tokio::fs;
-use toml;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tracing::{error, info, warn};
 
-use crate::ws::{ServerConfig, SERVER_CONFIGS};
+use crate::settings;
+use crate::ws::{SERVER_CONFIGS, ServerConfig};
 
+/// Legacy configuration file, also imported by a fresh SQLite store.
 const CONFIG_FILE: &str = "server_configs.toml";
+const CONFIG_DB: &str = "server_configs.sqlite";
 
-pub async fn save_configs() {
-    let configs = SERVER_CONFIGS.read().await;
-    info!("Attempting to save {} configs", configs.len());
+type StoreResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
-    let string_keyed_configs: HashMap<String, ServerConfig> = configs
+/// Where guild configurations are persisted. Updates go through one guild at
+/// a time so a command only ever touches its own guild's data.
+pub trait ConfigStore: Send + Sync {
+    fn load(&self) -> StoreResult<LoadResult>;
+
+    /// Loads again while the bot is running. Unlike at startup, anything
+    /// unreadable fails the whole reload so the running configuration stays.
+    fn reload(&self) -> StoreResult<LoadResult> {
+        self.load()
+    }
+
+    /// File to watch for hand edits, if the store is a plain file.
+    fn watched_path(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Stores `config` for `guild_id`, or removes the guild when it's `None`.
+    fn save_guild(&self, guild_id: u64, config: Option<&ServerConfig>) -> StoreResult<()>;
+
+    /// Replaces every stored guild with `configs`.
+    fn replace_all(&self, configs: &HashMap<u64, ServerConfig>) -> StoreResult<()>;
+}
+
+/// What came out of loading the stored configurations.
+#[derive(Debug, Default)]
+pub struct LoadResult {
+    pub configs: HashMap<u64, ServerConfig>,
+    /// Guild entries that couldn't be read, with the reason. They are left
+    /// out of `configs`.
+    pub failed: Vec<(String, String)>,
+    /// Where the original file was moved or copied to, after unreadable
+    /// entries or a layout upgrade.
+    pub backup: Option<PathBuf>,
+    /// Layout version the file was upgraded from, if it was.
+    pub migrated_from: Option<i64>,
+    /// What the upgrade changed.
+    pub migrations: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StoreKind {
+    Sqlite,
+    Toml,
+}
+
+#[derive(Clone, Debug)]
+pub struct StoreSettings {
+    pub kind: StoreKind,
+    pub path: PathBuf,
+    /// Refuse to start when any configuration can't be read, instead of
+    /// backing up the file and carrying on with the rest.
+    pub strict: bool,
+}
+
+impl StoreSettings {
+    /// Reads `DRAUGUR_CONFIG_STORE` (`sqlite`, the default, or `toml`),
+    /// `DRAUGUR_CONFIG_PATH` and `DRAUGUR_CONFIG_STRICT`.
+    pub fn from_env() -> Result<StoreSettings, String> {
+        let kind = match settings::var("DRAUGUR_CONFIG_STORE")
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str()
+        {
+            "" | "sqlite" => StoreKind::Sqlite,
+            "toml" => StoreKind::Toml,
+            other => return Err(format!("unknown DRAUGUR_CONFIG_STORE '{}'", other)),
+        };
+
+        let path = match settings::var("DRAUGUR_CONFIG_PATH") {
+            Ok(path) => settings::data_path(path),
+            Err(_) => match kind {
+                StoreKind::Sqlite => settings::data_path(CONFIG_DB),
+                StoreKind::Toml => settings::data_path(CONFIG_FILE),
+            },
+        };
+
+        let strict = settings::var("DRAUGUR_CONFIG_STRICT")
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+
+        Ok(StoreSettings { kind, path, strict })
+    }
+}
+
+static STORE: OnceCell<Box<dyn ConfigStore>> = OnceCell::new();
+
+/// Held across changing `SERVER_CONFIGS` and saving the change, and by
+/// reloads, so a reload can never swap out a change before it is saved.
+static CONFIG_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Proof that the caller holds `CONFIG_LOCK`.
+pub type ConfigLock = tokio::sync::MutexGuard<'static, ()>;
+
+/// Takes the configuration lock. Hold it from before changing a guild's
+/// configuration until `save_config` has returned.
+pub async fn lock_configs() -> ConfigLock {
+    CONFIG_LOCK.lock().await
+}
+
+/// How often the configuration file is checked for hand edits.
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Modification time of the watched file as of the last load or save, so
+/// the watcher skips the bot's own writes.
+static LAST_SEEN: Mutex<Option<SystemTime>> = Mutex::new(None);
+
+fn watched_mtime(store: &dyn ConfigStore) -> Option<SystemTime> {
+    fs::metadata(store.watched_path()?).ok()?.modified().ok()
+}
+
+fn remember_mtime(modified: Option<SystemTime>) {
+    if let Ok(mut last_seen) = LAST_SEEN.lock() {
+        *last_seen = modified;
+    }
+}
+
+/// Whether the watched file was modified since the bot last read or wrote it.
+fn changed_on_disk(store: &dyn ConfigStore) -> bool {
+    let modified = watched_mtime(store);
+    let last_seen = LAST_SEEN.lock().ok().and_then(|last_seen| *last_seen);
+    modified.is_some() && modified != last_seen
+}
+
+/// Persists the current configuration of `guild_id`, or its removal if the
+/// guild no longer has one. Failures are logged and returned, so a command
+/// can tell its user that the change won't survive a restart.
+pub async fn save_config(lock: &ConfigLock, guild_id: u64) -> Result<(), String> {
+    save_configs(lock, &[guild_id]).await
+}
+
+/// Persists the current configuration of every guild in `guild_ids`, like
+/// `save_config`. Changes to several guilds are saved together, so a reload
+/// before saving keeps all of them.
+pub async fn save_configs(_lock: &ConfigLock, guild_ids: &[u64]) -> Result<(), String> {
+    let Some(store) = STORE.get() else {
+        error!(
+            "configuration store not initialised, not saving guilds {:?}",
+            guild_ids
+        );
+        return Err("the configuration store isn't available".to_string());
+    };
+
+    let changes: Vec<(u64, Option<ServerConfig>)> = {
+        let configs = SERVER_CONFIGS.read().await;
+        guild_ids
+            .iter()
+            .map(|guild_id| (*guild_id, configs.get(guild_id).cloned()))
+            .collect()
+    };
+
+    // The store writes out its own copy, so a hand edit the watcher hasn't
+    // picked up yet is loaded first and these changes applied on top.
+    if changed_on_disk(store.as_ref()) {
+        info!("configuration changed on disk, reloading before saving");
+        if let Err(e) = reload_store(store.as_ref()).await {
+            error!(
+                "not saving guilds {:?}, the configuration changed on disk and cannot be reloaded: {}",
+                guild_ids, e
+            );
+            return Err(format!(
+                "the configuration file changed on disk and cannot be reloaded: {}",
+                e
+            ));
+        }
+        let mut configs = SERVER_CONFIGS.write().await;
+        for (guild_id, config) in &changes {
+            match config {
+                Some(config) => configs.insert(*guild_id, config.clone()),
+                None => configs.remove(guild_id),
+            };
+        }
+    }
+
+    let saved = tokio::task::spawn_blocking(move || {
+        for (guild_id, config) in &changes {
+            store
+                .save_guild(*guild_id, config.as_ref())
+                .map_err(|e| format!("guild {}: {}", guild_id, e))?;
+        }
+        Ok::<_, String>(())
+    })
+    .await;
+    remember_mtime(watched_mtime(store.as_ref()));
+    match saved {
+        Ok(Ok(())) => {
+            info!("saved configuration for guilds {:?}", guild_ids);
+            Ok(())
+        }
+        Ok(Err(e)) => {
+            error!("failed to save configuration for {}", e);
+            Err(e)
+        }
+        Err(e) => {
+            error!(
+                "configuration save for guilds {:?} panicked: {}",
+                guild_ids, e
+            );
+            Err("saving panicked".to_string())
+        }
+    }
+}
+
+/// Opens the configuration store and loads every guild into
+/// `SERVER_CONFIGS`. A store that can't be opened or read is an error, as
+/// starting empty would overwrite it on the next save.
+pub async fn load_configs() -> Result<(), String> {
+    let settings = StoreSettings::from_env()?;
+    let store = open_store(&settings)?;
+
+    let loaded = store
+        .load()
+        .map_err(|e| format!("cannot load configurations from {:?}: {}", settings.path, e))?;
+    info!(
+        "loaded {} server configurations from {:?}",
+        loaded.configs.len(),
+        settings.path
+    );
+
+    // Only the TOML store is watched, so edits to a leftover file would
+    // otherwise vanish without a trace.
+    let legacy = settings::data_path(CONFIG_FILE);
+    if settings.kind == StoreKind::Sqlite && legacy.exists() {
+        warn!(
+            "{:?} exists but configurations are stored in {:?}, edits to the file are ignored. \
+             Import it with --import-configs or set DRAUGUR_CONFIG_STORE=toml to use it",
+            legacy, settings.path
+        );
+    }
+
+    *SERVER_CONFIGS.write().await = loaded.configs;
+    remember_mtime(watched_mtime(store.as_ref()));
+    let _ = STORE.set(store);
+    Ok(())
+}
+
+fn open_store(settings: &StoreSettings) -> Result<Box<dyn ConfigStore>, String> {
+    match settings.kind {
+        StoreKind::Sqlite => {
+            let legacy = settings::data_path(CONFIG_FILE);
+            let store = SqliteStore::open(&settings.path, &legacy, settings.strict)
+                .map_err(|e| format!("cannot open {:?}: {}", settings.path, e))?;
+            Ok(Box::new(store))
+        }
+        StoreKind::Toml => Ok(Box::new(TomlStore::new(
+            settings.path.clone(),
+            settings.strict,
+        ))),
+    }
+}
+
+/// Replaces every stored configuration with the guilds in a
+/// `server_configs.toml` file, returning how many were imported. Any entry
+/// that can't be read fails the whole import. Run this while the bot is
+/// stopped, as a running bot keeps its own copy.
+pub fn import_configs(path: &Path) -> Result<usize, String> {
+    let data = fs::read_to_string(path).map_err(|e| format!("cannot read {:?}: {}", path, e))?;
+    let imported = configs_from_toml(&data)?;
+    if let Some((guild, reason)) = imported.failed.first() {
+        return Err(format!(
+            "cannot import {:?}, the entry for guild {} is unreadable: {}",
+            path, guild, reason
+        ));
+    }
+
+    let settings = StoreSettings::from_env()?;
+    let store = open_store(&settings)?;
+    store
+        .replace_all(&imported.configs)
+        .map_err(|e| format!("cannot write to {:?}: {}", settings.path, e))?;
+    Ok(imported.configs.len())
+}
+
+/// Writes every stored configuration to `path` in the `server_configs.toml`
+/// layout, returning how many guilds were exported.
+pub fn export_configs(path: &Path) -> Result<usize, String> {
+    let settings = StoreSettings::from_env()?;
+    let store = open_store(&settings)?;
+    let loaded = store
+        .load()
+        .map_err(|e| format!("cannot load configurations from {:?}: {}", settings.path, e))?;
+
+    let data = configs_to_toml(&loaded.configs).map_err(|e| e.to_string())?;
+    write_atomically(path, data.as_bytes())
+        .map_err(|e| format!("cannot write {:?}: {}", path, e))?;
+    Ok(loaded.configs.len())
+}
+
+/// Waits for any save in progress to finish. Every save is written through
+/// right away, so nothing else is buffered.
+pub async fn flush_configs() {
+    let _lock = lock_configs().await;
+}
+
+/// Re-reads the configuration store and swaps the result in as a whole,
+/// returning how many guilds were loaded. On any error the running
+/// configuration is left alone.
+pub async fn reload_configs() -> Result<usize, String> {
+    let store = STORE.get().ok_or("configuration store not initialised")?;
+    let _lock = lock_configs().await;
+    reload_store(store.as_ref()).await
+}
+
+/// Reloads `store` into `SERVER_CONFIGS`. The caller holds `CONFIG_LOCK`.
+async fn reload_store(store: &'static dyn ConfigStore) -> Result<usize, String> {
+    // Taken before reading, so an edit made during the reload is seen next time.
+    let modified = watched_mtime(store);
+    remember_mtime(modified);
+
+    let loaded = tokio::task::spawn_blocking(move || store.reload())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+
+    let count = loaded.configs.len();
+    *SERVER_CONFIGS.write().await = loaded.configs;
+    info!("reloaded {} server configurations", count);
+    Ok(count)
+}
+
+/// Reloads the configuration file whenever it's edited by hand. This polls
+/// the modification time, which also works on mounted volumes where file
+/// system events don't arrive.
+pub async fn watch_configs() {
+    let Some(store) = STORE.get() else {
+        return;
+    };
+    let Some(path) = store.watched_path() else {
+        return;
+    };
+    info!("watching {:?} for changes", path);
+
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        // Checked under the lock, as a save in progress may have written the
+        // file without recording its modification time yet.
+        let _lock = lock_configs().await;
+        if !changed_on_disk(store.as_ref()) {
+            continue;
+        }
+
+        info!("{:?} changed on disk, reloading", path);
+        if let Err(e) = reload_store(store.as_ref()).await {
+            error!(
+                "keeping the running configuration, cannot reload {:?}: {}",
+                path, e
+            );
+        }
+    }
+}
+
+/// Layout version of `server_configs.toml` written by this build.
+pub const CONFIG_VERSION: i64 = 2;
+
+/// Upgrades a parsed file by one version and describes what it changed.
+type FileMigration = fn(&mut toml::Table) -> Vec<String>;
+
+/// `FILE_MIGRATIONS[n]` upgrades a version `n + 1` file to version `n + 2`.
+const FILE_MIGRATIONS: &[FileMigration] = &[nest_guilds];
+
+/// Version 1 files had no `version` key and kept every guild at the top
+/// level. Version 2 moves them under `guilds` so other top-level keys can't
+/// clash with guild IDs.
+fn nest_guilds(table: &mut toml::Table) -> Vec<String> {
+    let guilds = std::mem::take(table);
+    let changes = vec![format!(
+        "moved {} guild entries under [guilds]",
+        guilds.len()
+    )];
+    table.insert("guilds".to_string(), toml::Value::Table(guilds));
+    changes
+}
+
+/// Brings a parsed file up to `CONFIG_VERSION`, returning the version it
+/// started at and the changes made. Files from a newer build are rejected
+/// rather than guessed at.
+pub fn migrate_config(table: &mut toml::Table) -> Result<(i64, Vec<String>), String> {
+    let version = match table.remove("version") {
+        None => 1,
+        Some(toml::Value::Integer(version)) => version,
+        Some(other) => return Err(format!("invalid configuration version {}", other)),
+    };
+
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "configuration version {} is newer than the supported version {}",
+            version, CONFIG_VERSION
+        ));
+    }
+    if version < 1 {
+        return Err(format!("invalid configuration version {}", version));
+    }
+
+    let mut changes = Vec::new();
+    for (step, migration) in FILE_MIGRATIONS
         .iter()
-        .map(|(k, v)| (k.to_string(), v.clone()))
-        .collect();
-
-    match toml::to_string_pretty(&string_keyed_configs) {
-        Ok(toml_string) => {
-            info!("Generated TOML: {}", toml_string);
-            match fs::write(CONFIG_FILE, toml_string).await {
-                Ok(_) => info!("Successfully wrote to {}", CONFIG_FILE),
-                Err(e) => error!("Failed to write file: {}", e),
-            }
+        .enumerate()
+        .skip(version as usize - 1)
+    {
+        for change in migration(table) {
+            changes.push(format!("version {} to {}: {}", step + 1, step + 2, change));
         }
+    }
+
+    table.insert("version".to_string(), toml::Value::Integer(CONFIG_VERSION));
+    Ok((version, changes))
+}
+
+#[derive(Serialize)]
+struct ConfigFile<'a> {
+    version: i64,
+    guilds: HashMap<String, &'a ServerConfig>,
+}
+
+/// Renders configurations in the current `server_configs.toml` layout.
+pub fn configs_to_toml(configs: &HashMap<u64, ServerConfig>) -> Result<String, toml::ser::Error> {
+    let file = ConfigFile {
+        version: CONFIG_VERSION,
+        guilds: configs
+            .iter()
+            .map(|(guild_id, config)| (guild_id.to_string(), config))
+            .collect(),
+    };
+
+    toml::to_string_pretty(&file)
+}
+
+/// Parses any supported `server_configs.toml` layout, one guild at a time so
+/// a single bad entry doesn't take the others down with it.
+pub fn configs_from_toml(data: &str) -> Result<LoadResult, String> {
+    let mut result = LoadResult::default();
+
+    let mut table = match data.parse::<toml::Table>() {
+        Ok(table) => table,
         Err(e) => {
-            error!("Failed to serialize to TOML: {}", e);
+            result.failed.push((
+                "(whole file)".to_string(),
+                e.to_string().trim_end().to_string(),
+            ));
+            return Ok(result);
+        }
+    };
+
+    let (version, changes) = migrate_config(&mut table)?;
+    if !changes.is_empty() {
+        result.migrated_from = Some(version);
+        result.migrations = changes;
+    }
+
+    let guilds = match table.remove("guilds") {
+        Some(toml::Value::Table(guilds)) => guilds,
+        Some(_) => {
+            result.failed.push((
+                "(whole file)".to_string(),
+                "`guilds` is not a table".to_string(),
+            ));
+            return Ok(result);
+        }
+        None => toml::Table::new(),
+    };
+
+    for (key, value) in guilds {
+        let Ok(guild_id) = key.parse::<u64>() else {
+            result.failed.push((key, "not a guild ID".to_string()));
+            continue;
+        };
+        match value.try_into::<ServerConfig>() {
+            Ok(config) => {
+                result.configs.insert(guild_id, config);
+            }
+            Err(e) => result.failed.push((key, e.message().to_string())),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Reads a `server_configs.toml` file, or returns `None` if there is none.
+/// Unreadable entries are logged and, unless `strict` is set, the file is
+/// moved to a timestamped backup before anything can overwrite it. In strict
+/// mode they are an error instead. An older layout is upgraded in place after
+/// copying the original aside.
+fn read_toml_file(path: &Path, strict: bool) -> StoreResult<Option<LoadResult>> {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut result = configs_from_toml(&data)?;
+
+    if !result.failed.is_empty() {
+        for (guild, reason) in &result.failed {
+            error!(
+                "cannot read configuration for guild {} in {:?}: {}",
+                guild, path, reason
+            );
+        }
+        if strict {
+            return Err(format!(
+                "{} configuration entries in {:?} are unreadable",
+                result.failed.len(),
+                path
+            )
+            .into());
         }
+
+        let backup = backup_path(path, &Utc::now().format("%Y%m%d-%H%M%S").to_string());
+        fs::rename(path, &backup)?;
+        warn!(
+            "moved {:?} to {:?}, continuing with the {} readable configurations",
+            path,
+            backup,
+            result.configs.len()
+        );
+        result.backup = Some(backup);
+        return Ok(Some(result));
+    }
+
+    if let Some(version) = result.migrated_from {
+        let backup = backup_path(path, &format!("v{}", version));
+        fs::copy(path, &backup)?;
+        for change in &result.migrations {
+            info!("migrated {:?}: {}", path, change);
+        }
+        write_atomically(path, configs_to_toml(&result.configs)?.as_bytes())?;
+        info!(
+            "upgraded {:?} to configuration version {}, the original is in {:?}",
+            path, CONFIG_VERSION, backup
+        );
+        result.backup = Some(backup);
+    }
+
+    Ok(Some(result))
+}
+
+fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".{}.bak", suffix));
+    PathBuf::from(backup)
+}
+
+/// Keeps every guild in a single TOML file. Each save writes a temporary
+/// file and renames it over the old one, so a crash never leaves a
+/// half-written file behind.
+pub struct TomlStore {
+    path: PathBuf,
+    strict: bool,
+    configs: Mutex<HashMap<u64, ServerConfig>>,
+}
+
+impl TomlStore {
+    pub fn new(path: PathBuf, strict: bool) -> TomlStore {
+        TomlStore {
+            path,
+            strict,
+            configs: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl TomlStore {
+    /// Keeps a copy of what was loaded, which later saves write out.
+    fn keep(&self, loaded: LoadResult) -> StoreResult<LoadResult> {
+        *self
+            .configs
+            .lock()
+            .map_err(|_| "configuration lock poisoned")? = loaded.configs.clone();
+        Ok(loaded)
+    }
+}
+
+impl ConfigStore for TomlStore {
+    /// A missing file is a first start, so it loads as empty.
+    fn load(&self) -> StoreResult<LoadResult> {
+        let loaded = read_toml_file(&self.path, self.strict)?.unwrap_or_default();
+        self.keep(loaded)
+    }
+
+    /// A file that went missing while the bot runs fails the reload, as
+    /// loading it as empty would drop every guild on the next save.
+    fn reload(&self) -> StoreResult<LoadResult> {
+        let loaded = read_toml_file(&self.path, true)?
+            .ok_or_else(|| format!("{:?} does not exist", self.path))?;
+        self.keep(loaded)
+    }
+
+    fn watched_path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn save_guild(&self, guild_id: u64, config: Option<&ServerConfig>) -> StoreResult<()> {
+        let mut configs = self
+            .configs
+            .lock()
+            .map_err(|_| "configuration lock poisoned")?;
+        match config {
+            Some(config) => configs.insert(guild_id, config.clone()),
+            None => configs.remove(&guild_id),
+        };
+
+        write_atomically(&self.path, configs_to_toml(&configs)?.as_bytes())?;
+        Ok(())
+    }
+
+    fn replace_all(&self, configs: &HashMap<u64, ServerConfig>) -> StoreResult<()> {
+        let mut stored = self
+            .configs
+            .lock()
+            .map_err(|_| "configuration lock poisoned")?;
+        write_atomically(&self.path, configs_to_toml(configs)?.as_bytes())?;
+        *stored = configs.clone();
+        Ok(())
+    }
+}
+
+/// Replaces `path` with `contents` through a temporary file, so a crash
+/// leaves either the old file or the new one.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Schema changes, applied in order. The database's `user_version` records
+/// how many have run.
+const SCHEMA_MIGRATIONS: &[&str] = &["CREATE TABLE guild_configs (
+        guild_id INTEGER PRIMARY KEY,
+        channel_id INTEGER NOT NULL,
+        disabled TEXT,
+        admin_role_id INTEGER
+    );
+    CREATE TABLE guild_follows (
+        guild_id INTEGER NOT NULL REFERENCES guild_configs (guild_id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        follow_id INTEGER NOT NULL,
+        PRIMARY KEY (guild_id, position)
+    );"];
+
+/// Keeps one row per guild in SQLite, updating a guild and its follows in a
+/// single transaction.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Opens the database and brings its schema up to date. A database
+    /// created just now imports the TOML file at `legacy_path` if one exists, in the
+    /// same transaction as the schema, so a failed import is retried on the
+    /// next start.
+    pub fn open(path: &Path, legacy_path: &Path, strict: bool) -> StoreResult<SqliteStore> {
+        let mut conn = Connection::open(path)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+
+        let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        let legacy = if version == 0 {
+            let legacy = read_toml_file(legacy_path, strict)?;
+            if legacy.is_none() {
+                // The automatic import only ever runs now, so a file that is
+                // only moved into place later has to be imported by hand.
+                warn!(
+                    "creating a new configuration database at {:?} with no {:?} to import. \
+                     If you are upgrading, stop the bot and run it once with \
+                     --import-configs <path to server_configs.toml>",
+                    path, legacy_path
+                );
+            }
+            Some(legacy.unwrap_or_default())
+        } else {
+            None
+        };
+
+        if version < SCHEMA_MIGRATIONS.len() {
+            let tx = conn.transaction()?;
+            for migration in &SCHEMA_MIGRATIONS[version..] {
+                tx.execute_batch(migration)?;
+            }
+            tx.pragma_update(None, "user_version", SCHEMA_MIGRATIONS.len())?;
+
+            if let Some(legacy) = &legacy {
+                for (guild_id, config) in &legacy.configs {
+                    write_guild(&tx, *guild_id, Some(config))?;
+                }
+            }
+            tx.commit()?;
+            info!(
+                "configuration schema migrated from version {} to {}",
+                version,
+                SCHEMA_MIGRATIONS.len()
+            );
+        }
+
+        if let Some(legacy) = legacy
+            && !legacy.configs.is_empty()
+        {
+            warn!(
+                "imported {} server configurations from {:?}, which is no longer used",
+                legacy.configs.len(),
+                legacy_path
+            );
+        }
+
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+impl ConfigStore for SqliteStore {
+    fn load(&self) -> StoreResult<LoadResult> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|_| "configuration lock poisoned")?;
+
+        let mut configs = HashMap::new();
+        let mut guilds = conn
+            .prepare("SELECT guild_id, channel_id, disabled, admin_role_id FROM guild_configs")?;
+        let rows = guilds.query_map([], |row| {
+            Ok((
+                row.get::<_, u64>(0)?,
+                ServerConfig {
+                    follow_ids: Vec::new(),
+                    channel_id: row.get(1)?,
+                    disabled: row.get(2)?,
+                    admin_role_id: row.get(3)?,
+                },
+            ))
+        })?;
+        for row in rows {
+            let (guild_id, config) = row?;
+            configs.insert(guild_id, config);
+        }
+
+        let mut follows = conn
+            .prepare("SELECT guild_id, follow_id FROM guild_follows ORDER BY guild_id, position")?;
+        let rows = follows.query_map([], |row| Ok((row.get::<_, u64>(0)?, row.get(1)?)))?;
+        for row in rows {
+            let (guild_id, follow_id) = row?;
+            if let Some(config) = configs.get_mut(&guild_id) {
+                config.follow_ids.push(follow_id);
+            }
+        }
+
+        Ok(LoadResult {
+            configs,
+            ..Default::default()
+        })
+    }
+
+    fn save_guild(&self, guild_id: u64, config: Option<&ServerConfig>) -> StoreResult<()> {
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|_| "configuration lock poisoned")?;
+        let tx = conn.transaction()?;
+        write_guild(&tx, guild_id, config)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn replace_all(&self, configs: &HashMap<u64, ServerConfig>) -> StoreResult<()> {
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|_| "configuration lock poisoned")?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM guild_configs", [])?;
+        for (guild_id, config) in configs {
+            write_guild(&tx, *guild_id, Some(config))?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+fn write_guild(conn: &Connection, guild_id: u64, config: Option<&ServerConfig>) -> StoreResult<()> {
+    let Some(config) = config else {
+        conn.execute(
+            "DELETE FROM guild_configs WHERE guild_id = ?1",
+            params![guild_id],
+        )?;
+        return Ok(());
+    };
+
+    conn.execute(
+        "INSERT INTO guild_configs (guild_id, channel_id, disabled, admin_role_id)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (guild_id) DO UPDATE SET
+            channel_id = excluded.channel_id,
+            disabled = excluded.disabled,
+            admin_role_id = excluded.admin_role_id",
+        params![
+            guild_id,
+            config.channel_id,
+            config.disabled,
+            config.admin_role_id
+        ],
+    )?;
+
+    conn.execute(
+        "DELETE FROM guild_follows WHERE guild_id = ?1",
+        params![guild_id],
+    )?;
+    let mut insert = conn
+        .prepare("INSERT INTO guild_follows (guild_id, position, follow_id) VALUES (?1, ?2, ?3)")?;
+    for (position, follow_id) in config.follow_ids.iter().enumerate() {
+        insert.execute(params![guild_id, position as i64, follow_id])?;
     }
+
+    Ok(())
 }
 
-pub async fn load_configs() {
-    if let Ok(data) = fs::read_to_string(CONFIG_FILE).await {
-        if let Ok(string_configs) = toml::from_str::<HashMap<String, ServerConfig>>(&data) {
-            let configs: HashMap<u64, ServerConfig> = string_configs
-                .into_iter()
-                .filter_map(|(k, v)| k.parse::<u64>().ok().map(|guild_id| (guild_id, v)))
-                .collect();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/configs")
+            .join(name);
+        fs::read_to_string(path).expect("fixture exists")
+    }
+
+    /// Copies a fixture into its own temporary directory, so tests that
+    /// rewrite or move the file don't interfere with each other.
+    fn fixture_copy(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "draugur-configs-{}-{}",
+            std::process::id(),
+            name.trim_end_matches(".toml")
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("server_configs.toml");
+        fs::write(&path, fixture(name)).unwrap();
+        path
+    }
+
+    fn expected_v1() -> HashMap<u64, ServerConfig> {
+        HashMap::from([
+            (
+                123456789012345678,
+                ServerConfig {
+                    follow_ids: vec![99000006, 98000001],
+                    channel_id: 223456789012345678,
+                    disabled: None,
+                    admin_role_id: None,
+                },
+            ),
+            (
+                323456789012345678,
+                ServerConfig {
+                    follow_ids: vec![],
+                    channel_id: 423456789012345678,
+                    disabled: None,
+                    admin_role_id: None,
+                },
+            ),
+        ])
+    }
+
+    #[test]
+    fn every_version_has_a_migration() {
+        assert_eq!(FILE_MIGRATIONS.len() as i64 + 1, CONFIG_VERSION);
+    }
+
+    #[test]
+    fn v1_is_migrated() {
+        let result = configs_from_toml(&fixture("v1.toml")).unwrap();
+
+        assert_eq!(result.configs, expected_v1());
+        assert!(result.failed.is_empty());
+        assert_eq!(result.migrated_from, Some(1));
+        assert_eq!(
+            result.migrations,
+            vec!["version 1 to 2: moved 2 guild entries under [guilds]"]
+        );
+    }
+
+    #[test]
+    fn v1_optional_fields_survive_migration() {
+        let result = configs_from_toml(&fixture("v1_disabled.toml")).unwrap();
+        let config = &result.configs[&123456789012345678];
+
+        assert_eq!(config.follow_ids, vec![30000142]);
+        assert_eq!(
+            config.disabled.as_deref(),
+            Some("the configured channel was deleted")
+        );
+        assert_eq!(config.admin_role_id, Some(523456789012345678));
+    }
+
+    #[test]
+    fn current_version_is_not_migrated() {
+        let result = configs_from_toml(&fixture("v2.toml")).unwrap();
+
+        assert_eq!(result.configs, expected_v1());
+        assert_eq!(result.migrated_from, None);
+        assert!(result.migrations.is_empty());
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let error = configs_from_toml(&fixture("future.toml")).unwrap_err();
+        assert!(error.contains("newer"), "{}", error);
+    }
+
+    #[test]
+    fn written_configs_read_back_unchanged() {
+        let written = configs_to_toml(&expected_v1()).unwrap();
+        let result = configs_from_toml(&written).unwrap();
+
+        assert_eq!(result.configs, expected_v1());
+        assert_eq!(result.migrated_from, None);
+    }
 
-            let mut server_configs = SERVER_CONFIGS.write().await;
-            *server_configs = configs.clone();
-            info!("loaded {} server configurations", configs.len());
+    #[test]
+    fn v1_file_is_upgraded_in_place_with_backup() {
+        let path = fixture_copy("v1.toml");
+
+        let result = read_toml_file(&path, false).unwrap().unwrap();
+        assert_eq!(result.configs, expected_v1());
+
+        let backup = result.backup.expect("original is backed up");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), fixture("v1.toml"));
+
+        let upgraded = configs_from_toml(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(upgraded.configs, expected_v1());
+        assert_eq!(upgraded.migrated_from, None);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn unreadable_entries_are_reported() {
+        let path = fixture_copy("v1_partial.toml");
+
+        assert!(read_toml_file(&path, true).is_err());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            fixture("v1_partial.toml")
+        );
+
+        let result = read_toml_file(&path, false).unwrap().unwrap();
+        assert_eq!(result.configs.len(), 1);
+        assert_eq!(result.failed.len(), 1);
+        assert_eq!(result.failed[0].0, "323456789012345678");
+        assert!(!path.exists());
+        assert!(result.backup.unwrap().exists());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn missing_file_loads_empty_but_fails_reload() {
+        let path = fixture_copy("v2.toml");
+        fs::remove_file(&path).unwrap();
+        let store = TomlStore::new(path.clone(), false);
+
+        assert!(store.load().unwrap().configs.is_empty());
+        assert!(store.reload().is_err());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("draugur-configs-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn sqlite_guilds_read_back_unchanged() {
+        let dir = temp_dir("sqlite");
+        let db = dir.join(CONFIG_DB);
+        let store = SqliteStore::open(&db, &dir.join(CONFIG_FILE), false).unwrap();
+
+        for (guild_id, config) in expected_v1() {
+            store.save_guild(guild_id, Some(&config)).unwrap();
         }
+        store
+            .save_guild(
+                623456789012345678,
+                Some(&ServerConfig {
+                    follow_ids: vec![30000142],
+                    channel_id: 723456789012345678,
+                    disabled: Some("the configured channel was deleted".to_string()),
+                    admin_role_id: Some(823456789012345678),
+                }),
+            )
+            .unwrap();
+        store.save_guild(623456789012345678, None).unwrap();
+        drop(store);
+
+        let store = SqliteStore::open(&db, &dir.join(CONFIG_FILE), false).unwrap();
+        assert_eq!(store.load().unwrap().configs, expected_v1());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn new_sqlite_store_imports_legacy_toml_once() {
+        let dir = temp_dir("legacy");
+        let legacy = dir.join(CONFIG_FILE);
+        fs::write(&legacy, fixture("v1.toml")).unwrap();
+        let db = dir.join(CONFIG_DB);
+
+        let store = SqliteStore::open(&db, &legacy, false).unwrap();
+        assert_eq!(store.load().unwrap().configs, expected_v1());
+        store.save_guild(323456789012345678, None).unwrap();
+        drop(store);
+
+        let store = SqliteStore::open(&db, &legacy, false).unwrap();
+        assert_eq!(store.load().unwrap().configs.len(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn replace_all_drops_guilds_not_given() {
+        let dir = temp_dir("replace");
+        let store = SqliteStore::open(&dir.join(CONFIG_DB), &dir.join(CONFIG_FILE), false).unwrap();
+        store
+            .save_guild(
+                623456789012345678,
+                Some(&ServerConfig {
+                    follow_ids: vec![30000142],
+                    channel_id: 723456789012345678,
+                    disabled: None,
+                    admin_role_id: None,
+                }),
+            )
+            .unwrap();
+
+        store.replace_all(&expected_v1()).unwrap();
+        assert_eq!(store.load().unwrap().configs, expected_v1());
+
+        fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -0,0 +1,281 @@
+use chrono::{DateTime, Utc};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::fs;
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, warn};
+
+use crate::configs::write_atomically;
+use crate::settings;
+
+const DEFAULT_WINDOW_MINUTES: i64 = 60;
+const DEFAULT_CAPACITY: usize = 10_000;
+const PERSIST_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug)]
+pub struct DedupSettings {
+    pub window: chrono::Duration,
+    pub capacity: usize,
+    pub path: Option<PathBuf>,
+}
+
+impl DedupSettings {
+    /// Reads `DRAUGUR_DEDUP_WINDOW_MINUTES`, `DRAUGUR_DEDUP_CAPACITY` and the
+    /// optional `DRAUGUR_DEDUP_FILE` used to persist seen IDs across restarts.
+    pub fn from_env() -> DedupSettings {
+        let window = settings::var("DRAUGUR_DEDUP_WINDOW_MINUTES")
+            .ok()
+            .and_then(|value| value.parse::<i64>().ok())
+            .filter(|minutes| *minutes > 0)
+            .unwrap_or(DEFAULT_WINDOW_MINUTES);
+
+        let capacity = settings::var("DRAUGUR_DEDUP_CAPACITY")
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .filter(|capacity| *capacity > 0)
+            .unwrap_or(DEFAULT_CAPACITY);
+
+        let path = settings::var("DRAUGUR_DEDUP_FILE")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .map(settings::data_path);
+
+        DedupSettings {
+            window: chrono::Duration::minutes(window),
+            capacity,
+            path,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SeenKill {
+    killmail_id: i64,
+    seen_at: i64,
+}
+
+/// A bounded set of recently seen killmail IDs. Entries expire after
+/// `window`, and the oldest ones are evicted once `capacity` is reached.
+pub struct DedupSet {
+    window: chrono::Duration,
+    capacity: usize,
+    seen: HashMap<i64, DateTime<Utc>>,
+    order: VecDeque<i64>,
+    dirty: bool,
+}
+
+impl DedupSet {
+    pub fn new(window: chrono::Duration, capacity: usize) -> DedupSet {
+        DedupSet {
+            window,
+            capacity,
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+            dirty: false,
+        }
+    }
+
+    /// Records `killmail_id` and returns `true` if it wasn't seen within the
+    /// window.
+    pub fn insert(&mut self, killmail_id: i64, now: DateTime<Utc>) -> bool {
+        self.expire(now);
+
+        if self.seen.contains_key(&killmail_id) {
+            return false;
+        }
+
+        while self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+
+        self.seen.insert(killmail_id, now);
+        self.order.push_back(killmail_id);
+        self.dirty = true;
+        true
+    }
+
+    fn expire(&mut self, now: DateTime<Utc>) {
+        let cutoff = now - self.window;
+        while let Some(oldest) = self.order.front() {
+            match self.seen.get(oldest) {
+                Some(seen_at) if *seen_at >= cutoff => break,
+                _ => {
+                    let oldest = *oldest;
+                    self.order.pop_front();
+                    self.seen.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    fn snapshot(&self) -> Vec<SeenKill> {
+        self.order
+            .iter()
+            .filter_map(|id| {
+                self.seen.get(id).map(|seen_at| SeenKill {
+                    killmail_id: *id,
+                    seen_at: seen_at.timestamp(),
+                })
+            })
+            .collect()
+    }
+
+    fn restore(&mut self, kills: Vec<SeenKill>) {
+        for kill in kills {
+            if let Some(seen_at) = DateTime::from_timestamp(kill.seen_at, 0) {
+                self.insert(kill.killmail_id, seen_at);
+            }
+        }
+        self.expire(Utc::now());
+        self.dirty = false;
+    }
+}
+
+pub static SEEN_KILLS: Lazy<Mutex<DedupSet>> = Lazy::new(|| {
+    let settings = DEDUP_SETTINGS.get_or_init(DedupSettings::from_env);
+    Mutex::new(DedupSet::new(settings.window, settings.capacity))
+});
+
+static DEDUP_SETTINGS: OnceCell<DedupSettings> = OnceCell::new();
+
+/// Returns `true` the first time a killmail ID is seen within the window.
+pub async fn is_new_kill(killmail_id: i64) -> bool {
+    let is_new = SEEN_KILLS.lock().await.insert(killmail_id, Utc::now());
+    if !is_new {
+        debug!("skipping duplicate killmail {}", killmail_id);
+    }
+    is_new
+}
+
+/// Loads the persisted dedup set, if configured, and starts a background task
+/// that writes it back whenever it changed.
+pub async fn init_dedup() {
+    let settings = DEDUP_SETTINGS.get_or_init(DedupSettings::from_env);
+    let Some(path) = settings.path.clone() else {
+        return;
+    };
+
+    match fs::read_to_string(&path).await {
+        Ok(data) => match serde_json::from_str::<Vec<SeenKill>>(&data) {
+            Ok(kills) => {
+                let mut seen = SEEN_KILLS.lock().await;
+                seen.restore(kills);
+                info!("restored {} recently seen killmail(s)", seen.order.len());
+            }
+            Err(e) => warn!("ignoring unreadable dedup file {:?}: {}", path, e),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!("cannot read dedup file {:?}: {}", path, e),
+    }
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(PERSIST_INTERVAL).await;
+            save_dedup().await;
+        }
+    });
+}
+
+/// Writes the dedup set to disk if persistence is enabled and it changed.
+pub async fn save_dedup() {
+    let Some(path) = DEDUP_SETTINGS.get().and_then(|s| s.path.clone()) else {
+        return;
+    };
+
+    let snapshot = {
+        let mut seen = SEEN_KILLS.lock().await;
+        if !seen.dirty {
+            return;
+        }
+        seen.dirty = false;
+        seen.snapshot()
+    };
+
+    let data = match serde_json::to_string(&snapshot) {
+        Ok(data) => data,
+        Err(e) => {
+            error!("failed to serialize dedup set: {}", e);
+            return;
+        }
+    };
+
+    // Written atomically, as a file cut short by a crash would be ignored on
+    // the next start and every recent kill posted again.
+    let written = {
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || write_atomically(&path, data.as_bytes())).await
+    };
+    match written {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => error!("failed to write dedup file {:?}: {}", path, e),
+        Err(e) => error!("writing dedup file {:?} panicked: {}", path, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(capacity: usize) -> DedupSet {
+        DedupSet::new(chrono::Duration::minutes(60), capacity)
+    }
+
+    #[test]
+    fn duplicate_within_window_is_rejected() {
+        let mut seen = set(10);
+        let now = Utc::now();
+
+        assert!(seen.insert(1, now));
+        assert!(!seen.insert(1, now + chrono::Duration::minutes(59)));
+    }
+
+    #[test]
+    fn kill_is_accepted_again_after_window() {
+        let mut seen = set(10);
+        let now = Utc::now();
+
+        assert!(seen.insert(1, now));
+        assert!(seen.insert(1, now + chrono::Duration::minutes(61)));
+    }
+
+    #[test]
+    fn oldest_kill_is_evicted_at_capacity() {
+        let mut seen = set(2);
+        let now = Utc::now();
+
+        assert!(seen.insert(1, now));
+        assert!(seen.insert(2, now));
+        assert!(seen.insert(3, now));
+
+        assert_eq!(seen.order, [2, 3]);
+        assert!(seen.insert(1, now));
+        assert!(!seen.insert(3, now));
+    }
+
+    #[test]
+    fn restore_drops_expired_kills() {
+        let mut seen = set(10);
+        let now = Utc::now();
+
+        seen.restore(vec![
+            SeenKill {
+                killmail_id: 1,
+                seen_at: (now - chrono::Duration::minutes(90)).timestamp(),
+            },
+            SeenKill {
+                killmail_id: 2,
+                seen_at: (now - chrono::Duration::minutes(10)).timestamp(),
+            },
+        ]);
+
+        assert_eq!(seen.order, [2]);
+        assert!(!seen.dirty);
+        assert!(seen.insert(1, now));
+        assert!(!seen.insert(2, now));
+    }
+}
@@ -0,0 +1,219 @@
+use serde::Serialize;
+use std::future::Future;
+
+use crate::esi;
+use crate::models::{Attacker, Killmail, Victim, Zkb};
+
+/// The final blow of a killmail with its names resolved.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FinalBlow {
+    pub character_id: i64,
+    pub name: String,
+    pub ship_type_id: i64,
+    pub ship_name: String,
+    pub corporation_id: i64,
+    pub corporation_name: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ValueBreakdown {
+    pub fitted: f64,
+    pub dropped: f64,
+    pub destroyed: f64,
+    pub total: f64,
+}
+
+/// A killmail with everything the bot shows about it resolved through ESI.
+///
+/// It is built once per killmail and shared by every consumer: embeds, the
+/// activity status and the archive.
+#[derive(Clone, Debug, Serialize)]
+pub struct EnrichedKill {
+    pub killmail: Killmail,
+    pub zkb: Zkb,
+    pub victim_name: String,
+    pub victim_security_status: f64,
+    pub victim_ship_name: String,
+    pub victim_corp_name: String,
+    pub final_blow: FinalBlow,
+    pub system_name: String,
+    pub system_security: f64,
+    pub constellation_name: String,
+    pub region_id: i64,
+    pub region_name: String,
+    pub value: ValueBreakdown,
+}
+
+impl EnrichedKill {
+    pub async fn new(km: Killmail, zkb: Zkb) -> EnrichedKill {
+        let fb_attacker = final_blow_attacker(&km.attackers)
+            .cloned()
+            .unwrap_or_default();
+
+        let (victim, victim_ship, victim_corp, final_blow, location) = tokio::join!(
+            lookup(km.victim.character_id, esi::Character::get_character),
+            lookup(km.victim.ship_type_id, esi::Ship::get_ship),
+            lookup(km.victim.corporation_id, esi::Corporation::get_corp),
+            resolve_final_blow(&fb_attacker),
+            resolve_location(km.solar_system_id),
+        );
+
+        let (victim_name, victim_security_status) = match victim {
+            Some(character) => (character.name, character.security_status),
+            _ => ("Unknown".to_string(), 0.0),
+        };
+
+        let (system_name, system_security, constellation_name, region_id, region_name) = location;
+
+        let value = ValueBreakdown {
+            fitted: zkb.fitted_value,
+            dropped: zkb.dropped_value,
+            destroyed: zkb.destroyed_value,
+            total: zkb.total_value,
+        };
+
+        EnrichedKill {
+            victim_name,
+            victim_security_status,
+            victim_ship_name: victim_ship.map_or_else(|| "Unknown".into(), |ship| ship.name),
+            victim_corp_name: victim_corp.map_or_else(|| "Unknown".into(), |corp| corp.name),
+            final_blow,
+            system_name,
+            system_security,
+            constellation_name,
+            region_id,
+            region_name,
+            value,
+            killmail: km,
+            zkb,
+        }
+    }
+
+    /// A made-up killmail used to preview what posts look like without
+    /// waiting for a real kill or touching ESI.
+    pub fn sample() -> EnrichedKill {
+        let killmail = Killmail {
+            attackers: vec![Attacker {
+                character_id: 2113678525,
+                corporation_id: 1000125,
+                final_blow: true,
+                ship_type_id: 17738,
+                ..Default::default()
+            }],
+            killmail_id: 0,
+            killmail_time: "2003-05-06T12:00:00Z".to_string(),
+            solar_system_id: 30000142,
+            victim: Victim {
+                character_id: 0,
+                corporation_id: 1000125,
+                ship_type_id: 587,
+                ..Default::default()
+            },
+        };
+
+        let zkb = Zkb {
+            fitted_value: 12_000_000.0,
+            dropped_value: 4_000_000.0,
+            destroyed_value: 8_000_000.0,
+            total_value: 12_000_000.0,
+            solo: true,
+            ..Default::default()
+        };
+
+        EnrichedKill {
+            victim_name: "Sample Pilot".to_string(),
+            victim_security_status: 0.0,
+            victim_ship_name: "Rifter".to_string(),
+            victim_corp_name: "Sample Corporation".to_string(),
+            final_blow: FinalBlow {
+                character_id: 2113678525,
+                name: "Sample Hunter".to_string(),
+                ship_type_id: 17738,
+                ship_name: "Machariel".to_string(),
+                corporation_id: 1000125,
+                corporation_name: "Sample Corporation".to_string(),
+            },
+            system_name: "Jita".to_string(),
+            system_security: 0.9,
+            constellation_name: "Kimotoro".to_string(),
+            region_id: 10000002,
+            region_name: "The Forge".to_string(),
+            value: ValueBreakdown {
+                fitted: zkb.fitted_value,
+                dropped: zkb.dropped_value,
+                destroyed: zkb.destroyed_value,
+                total: zkb.total_value,
+            },
+            killmail,
+            zkb,
+        }
+    }
+}
+
+/// Returns the attacker that landed the final blow, if any.
+pub fn final_blow_attacker(attackers: &[Attacker]) -> Option<&Attacker> {
+    attackers.iter().find(|attacker| attacker.final_blow)
+}
+
+/// Looks `id` up through `fetch`, treating a failed request as unknown.
+///
+/// An ID of 0 (NPC attackers, pilots without a corporation, a missing final
+/// blow) has nothing behind it, and asking ESI anyway costs a 404 against its
+/// error limit, so it is reported as unknown without a request.
+async fn lookup<T, F, Fut>(id: i64, fetch: F) -> Option<T>
+where
+    F: FnOnce(i64) -> Fut,
+    Fut: Future<Output = Result<T, reqwest::Error>>,
+{
+    if id == 0 {
+        return None;
+    }
+    fetch(id).await.ok()
+}
+
+async fn resolve_final_blow(attacker: &Attacker) -> FinalBlow {
+    let (character, ship, corp) = tokio::join!(
+        lookup(attacker.character_id, esi::Character::get_character),
+        lookup(attacker.ship_type_id, esi::Ship::get_ship),
+        lookup(attacker.corporation_id, esi::Corporation::get_corp),
+    );
+
+    FinalBlow {
+        character_id: attacker.character_id,
+        name: character.map_or_else(|| "Unknown".into(), |c| c.name),
+        ship_type_id: attacker.ship_type_id,
+        ship_name: ship.map_or_else(|| "Unknown".into(), |s| s.name),
+        corporation_id: attacker.corporation_id,
+        corporation_name: corp.map_or_else(|| "Unknown".into(), |c| c.name),
+    }
+}
+
+/// Resolves a solar system to its name, security status, constellation name,
+/// region ID and region name.
+///
+/// Each step needs the ID from the one before it, so the chain stops at the
+/// first lookup that fails instead of asking ESI about ID 0.
+async fn resolve_location(system_id: i64) -> (String, f64, String, i64, String) {
+    let unknown = || "Unknown".to_string();
+
+    let Some(sys) = lookup(system_id, esi::System::get_system).await else {
+        return (unknown(), 0.0, unknown(), 0, unknown());
+    };
+
+    let Some(constel) = lookup(sys.constellation_id, esi::Constellation::get_constellation).await
+    else {
+        return (sys.name, sys.security_status, unknown(), 0, unknown());
+    };
+
+    let region_name = lookup(constel.region_id, esi::Region::get_region)
+        .await
+        .map_or_else(unknown, |reg| reg.name);
+
+    (
+        sys.name,
+        sys.security_status,
+        constel.name,
+        constel.region_id,
+        region_name,
+    )
+}
@@ -1,6 +1,61 @@
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::metrics::{ESI_CACHE_LOOKUPS, ESI_ERRORS, ESI_REQUEST_DURATION, ESI_REQUESTS};
 
 const URL_BASE: &str = "https://esi.evetech.net/latest/";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Shared so connections are reused, with timeouts so a hung request can't
+// hold up whoever is waiting on it.
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+});
+
+// Universe data (ship types, systems, constellations, regions) never changes,
+// so it is cached for the lifetime of the process.
+static SHIPS: Lazy<Mutex<HashMap<i64, Ship>>> = Lazy::new(Default::default);
+static SYSTEMS: Lazy<Mutex<HashMap<i64, System>>> = Lazy::new(Default::default);
+static CONSTELLATIONS: Lazy<Mutex<HashMap<i64, Constellation>>> = Lazy::new(Default::default);
+static REGIONS: Lazy<Mutex<HashMap<i64, Region>>> = Lazy::new(Default::default);
+
+fn cached<T: Clone>(cache: &Mutex<HashMap<i64, T>>, name: &str, id: i64) -> Option<T> {
+    let found = cache.lock().ok()?.get(&id).cloned();
+    let result = if found.is_some() { "hit" } else { "miss" };
+    ESI_CACHE_LOOKUPS.with_label_values(&[name, result]).inc();
+    found
+}
+
+/// Fetches and decodes `url`, recording the request under `endpoint`.
+async fn get_json<T: DeserializeOwned>(endpoint: &str, url: String) -> Result<T, reqwest::Error> {
+    ESI_REQUESTS.with_label_values(&[endpoint]).inc();
+    let timer = ESI_REQUEST_DURATION
+        .with_label_values(&[endpoint])
+        .start_timer();
+
+    let result = async { CLIENT.get(url).send().await?.json::<T>().await }.await;
+
+    timer.observe_duration();
+    if result.is_err() {
+        ESI_ERRORS.with_label_values(&[endpoint]).inc();
+    }
+    result
+}
+
+fn store<T>(cache: &Mutex<HashMap<i64, T>>, id: i64, value: T) {
+    if let Ok(mut cache) = cache.lock() {
+        cache.insert(id, value);
+    }
+}
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Character {
@@ -12,7 +67,7 @@ impl Character {
     pub async fn get_character(id: i64) -> Result<Character, reqwest::Error> {
         let get_url = format!("{}characters/{}/?datasource=tranquility", URL_BASE, id);
 
-        let response: Character = reqwest::get(get_url).await?.json().await?;
+        let response: Character = get_json("characters", get_url).await?;
 
         Ok(response)
     }
@@ -25,12 +80,17 @@ pub struct Ship {
 
 impl Ship {
     pub async fn get_ship(id: i64) -> Result<Ship, reqwest::Error> {
+        if let Some(cached) = cached(&SHIPS, "ship", id) {
+            return Ok(cached);
+        }
+
         let get_url = format!(
             "{}universe/types/{}/?datasource=tranquility&language=en",
             URL_BASE, id
         );
 
-        let response: Ship = reqwest::get(get_url).await?.json().await?;
+        let response: Ship = get_json("types", get_url).await?;
+        store(&SHIPS, id, response.clone());
 
         Ok(response)
     }
@@ -45,12 +105,17 @@ pub struct System {
 
 impl System {
     pub async fn get_system(id: i64) -> Result<System, reqwest::Error> {
+        if let Some(cached) = cached(&SYSTEMS, "system", id) {
+            return Ok(cached);
+        }
+
         let get_url = format!(
             "{}universe/systems/{}/?datasource=tranquility&language=en",
             URL_BASE, id
         );
 
-        let response: System = reqwest::get(get_url).await?.json().await?;
+        let response: System = get_json("systems", get_url).await?;
+        store(&SYSTEMS, id, response.clone());
 
         Ok(response)
     }
@@ -64,12 +129,17 @@ pub struct Constellation {
 
 impl Constellation {
     pub async fn get_constellation(id: i64) -> Result<Constellation, reqwest::Error> {
+        if let Some(cached) = cached(&CONSTELLATIONS, "constellation", id) {
+            return Ok(cached);
+        }
+
         let get_url = format!(
             "{}universe/constellations/{}/?datasource=tranquility&language=en",
             URL_BASE, id
         );
 
-        let response: Constellation = reqwest::get(get_url).await?.json().await?;
+        let response: Constellation = get_json("constellations", get_url).await?;
+        store(&CONSTELLATIONS, id, response.clone());
 
         Ok(response)
     }
@@ -82,12 +152,17 @@ pub struct Region {
 
 impl Region {
     pub async fn get_region(id: i64) -> Result<Region, reqwest::Error> {
+        if let Some(cached) = cached(&REGIONS, "region", id) {
+            return Ok(cached);
+        }
+
         let get_url = format!(
             "{}universe/regions/{}/?datasource=tranquility&language=en",
             URL_BASE, id
         );
 
-        let response: Region = reqwest::get(get_url).await?.json().await?;
+        let response: Region = get_json("regions", get_url).await?;
+        store(&REGIONS, id, response.clone());
 
         Ok(response)
     }
@@ -102,8 +177,46 @@ impl Corporation {
     pub async fn get_corp(id: i64) -> Result<Corporation, reqwest::Error> {
         let get_url = format!("{}corporations/{}/?datasource=tranquility", URL_BASE, id);
 
-        let response: Corporation = reqwest::get(get_url).await?.json().await?;
+        let response: Corporation = get_json("corporations", get_url).await?;
 
         Ok(response)
     }
 }
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Name {
+    pub id: i64,
+    pub name: String,
+    pub category: String,
+}
+
+impl Name {
+    /// Resolves any mix of character, corporation, alliance, system and type
+    /// IDs in one request. ESI fails the whole request if any ID is unknown.
+    pub async fn get_names(ids: &[i64]) -> Result<Vec<Name>, reqwest::Error> {
+        let post_url = format!("{}universe/names/?datasource=tranquility", URL_BASE);
+
+        ESI_REQUESTS.with_label_values(&["names"]).inc();
+        let timer = ESI_REQUEST_DURATION
+            .with_label_values(&["names"])
+            .start_timer();
+
+        let response = async {
+            CLIENT
+                .post(post_url)
+                .json(ids)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Vec<Name>>()
+                .await
+        }
+        .await;
+
+        timer.observe_duration();
+        if response.is_err() {
+            ESI_ERRORS.with_label_values(&["names"]).inc();
+        }
+        response
+    }
+}
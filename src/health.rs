@@ -0,0 +1,144 @@
+use once_cell::sync::Lazy;
+use serenity::gateway::{ConnectionStage, ShardStageUpdateEvent};
+use serenity::model::id::ShardId;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::settings;
+use crate::shutdown::is_shutting_down;
+
+const DEFAULT_FEED_TIMEOUT_MINUTES: u64 = 10;
+/// Resumes and reconnects are routine, so the gateway only counts as down
+/// once it has stayed disconnected for this long.
+const GATEWAY_GRACE: Duration = Duration::from_secs(60);
+
+struct FeedState {
+    started: Option<Instant>,
+    last_kill: Option<Instant>,
+}
+
+struct GatewayState {
+    shards: HashMap<ShardId, ConnectionStage>,
+    disconnected_since: Option<Instant>,
+}
+
+static CACHE_READY: AtomicBool = AtomicBool::new(false);
+
+static FEED: Lazy<Mutex<FeedState>> = Lazy::new(|| {
+    Mutex::new(FeedState {
+        started: None,
+        last_kill: None,
+    })
+});
+
+static GATEWAY: Lazy<Mutex<GatewayState>> = Lazy::new(|| {
+    Mutex::new(GatewayState {
+        shards: HashMap::new(),
+        disconnected_since: Some(Instant::now()),
+    })
+});
+
+static FEED_TIMEOUT: Lazy<Duration> = Lazy::new(|| {
+    let minutes = settings::var("DRAUGUR_FEED_TIMEOUT_MINUTES")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_FEED_TIMEOUT_MINUTES);
+    Duration::from_secs(minutes * 60)
+});
+
+pub fn set_cache_ready() {
+    CACHE_READY.store(true, Ordering::Relaxed);
+}
+
+pub fn feed_started() {
+    FEED.lock().unwrap_or_else(PoisonError::into_inner).started = Some(Instant::now());
+}
+
+/// Records that the kill source delivered a killmail, duplicate or not.
+pub fn kill_received() {
+    FEED.lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .last_kill = Some(Instant::now());
+}
+
+pub fn shard_stage_changed(event: &ShardStageUpdateEvent) {
+    let mut gateway = GATEWAY.lock().unwrap_or_else(PoisonError::into_inner);
+    gateway.shards.insert(event.shard_id, event.new);
+
+    let connected = gateway
+        .shards
+        .values()
+        .all(|stage| *stage == ConnectionStage::Connected);
+    if connected {
+        gateway.disconnected_since = None;
+    } else if gateway.disconnected_since.is_none() {
+        gateway.disconnected_since = Some(Instant::now());
+    }
+}
+
+/// How long it has been since the feed last delivered a killmail, or since
+/// it started if it hasn't delivered one yet. `None` before it starts.
+fn feed_idle() -> Option<Duration> {
+    let feed = FEED.lock().unwrap_or_else(PoisonError::into_inner);
+    feed.last_kill.or(feed.started).map(|since| since.elapsed())
+}
+
+/// Problems that mean the bot should be restarted: a feed that stopped
+/// delivering killmails, or a gateway connection that stayed down.
+pub fn health_problems() -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if let Some(idle) = feed_idle()
+        && idle > *FEED_TIMEOUT
+    {
+        problems.push(format!(
+            "kill feed has not received a killmail for {}s",
+            idle.as_secs()
+        ));
+    }
+
+    if let Some(since) = GATEWAY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .disconnected_since
+        && since.elapsed() > GATEWAY_GRACE
+    {
+        problems.push(format!(
+            "Discord gateway disconnected for {}s",
+            since.elapsed().as_secs()
+        ));
+    }
+
+    problems
+}
+
+/// Reasons the bot can't do its job yet, or anymore: the cache isn't
+/// ready, no killmail has arrived recently, or a shutdown has started.
+pub fn readiness_problems() -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if is_shutting_down() {
+        problems.push("shutting down".to_string());
+    }
+
+    if !CACHE_READY.load(Ordering::Relaxed) {
+        problems.push("Discord cache not ready".to_string());
+    }
+
+    match FEED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .last_kill
+    {
+        None => problems.push("no killmail received yet".to_string()),
+        Some(last_kill) if last_kill.elapsed() > *FEED_TIMEOUT => problems.push(format!(
+            "no killmail received for {}s",
+            last_kill.elapsed().as_secs()
+        )),
+        Some(_) => {}
+    }
+
+    problems
+}
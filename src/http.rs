@@ -0,0 +1,129 @@
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{error, info, warn};
+
+use crate::health::{health_problems, readiness_problems};
+use crate::metrics::render_metrics;
+use crate::settings;
+
+/// Requests are a single line plus a few headers, nothing larger is read.
+const MAX_REQUEST_SIZE: usize = 8192;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn text(status: &'static str, body: &str) -> Response {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.to_string(),
+        }
+    }
+}
+
+/// Serves `/metrics` for Prometheus and the `/healthz` and `/readyz` probes
+/// on `DRAUGUR_HTTP_ADDR`, if it is set.
+pub async fn serve_http() {
+    let Ok(addr) = settings::var("DRAUGUR_HTTP_ADDR") else {
+        return;
+    };
+
+    let listener = match TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("cannot listen on {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("serving metrics and health checks on http://{}", addr);
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle_connection(stream));
+            }
+            Err(e) => {
+                warn!("cannot accept HTTP connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream) {
+    let request = match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await {
+        Ok(Ok(request)) => request,
+        _ => return,
+    };
+
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default();
+
+    let response = route(method, path).await;
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+
+    let _ = stream.write_all(head.as_bytes()).await;
+    if method != "HEAD" {
+        let _ = stream.write_all(response.body.as_bytes()).await;
+    }
+    let _ = stream.shutdown().await;
+}
+
+/// Reads up to the end of the request headers and returns the request line.
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 1024];
+
+    while !buffer.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 || buffer.len() + read > MAX_REQUEST_SIZE {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&buffer);
+    Ok(request.lines().next().unwrap_or_default().to_string())
+}
+
+async fn route(method: &str, path: &str) -> Response {
+    if method != "GET" && method != "HEAD" {
+        return Response::text("405 Method Not Allowed", "method not allowed\n");
+    }
+
+    match path {
+        "/metrics" => Response {
+            status: "200 OK",
+            content_type: "text/plain; version=0.0.4; charset=utf-8",
+            body: render_metrics().await,
+        },
+        "/healthz" => check(health_problems()),
+        "/readyz" => check(readiness_problems()),
+        _ => Response::text("404 Not Found", "not found\n"),
+    }
+}
+
+/// Answers a probe with `ok`, or with 503 and one problem per line.
+fn check(problems: Vec<String>) -> Response {
+    if problems.is_empty() {
+        Response::text("200 OK", "ok\n")
+    } else {
+        Response::text(
+            "503 Service Unavailable",
+            &format!("{}\n", problems.join("\n")),
+        )
+    }
+}
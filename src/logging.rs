@@ -0,0 +1,30 @@
+use tracing_subscriber::EnvFilter;
+
+use crate::settings;
+
+const DEFAULT_LOG_LEVEL: &str = "info";
+
+/// Sets up logging from `DRAUGUR_LOG_LEVEL`, a filter such as
+/// `info,draugur::outbound=debug`, and `DRAUGUR_LOG_FORMAT`, either `text`
+/// or `json` for log aggregation. JSON lines carry the fields of the
+/// killmail and delivery spans they were logged in.
+pub fn init_logging() -> Result<(), String> {
+    let level =
+        settings::var("DRAUGUR_LOG_LEVEL").unwrap_or_else(|_| DEFAULT_LOG_LEVEL.to_string());
+    let filter = EnvFilter::try_new(&level)
+        .map_err(|e| format!("invalid DRAUGUR_LOG_LEVEL '{}': {}", level, e))?;
+
+    let format = settings::var("DRAUGUR_LOG_FORMAT").unwrap_or_default();
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match format.to_lowercase().as_str() {
+        "" | "text" => builder.init(),
+        "json" => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
+        other => return Err(format!("unknown DRAUGUR_LOG_FORMAT '{}'", other)),
+    }
+
+    Ok(())
+}
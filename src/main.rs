@@ -1,57 +1,116 @@
+pub mod archive;
+pub mod cleanup;
 pub mod commands;
 pub mod configs;
+pub mod dedup;
+pub mod enrich;
 pub mod esi;
+pub mod health;
 pub mod helpers;
+pub mod http;
+pub mod logging;
+pub mod metrics;
 pub mod models;
 pub mod msg;
+pub mod outbound;
+pub mod settings;
+pub mod shutdown;
+pub mod sources;
+pub mod supervisor;
 pub mod ws;
+pub mod zkill;
 
-use std::env;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
-use log::{error, info};
-use serenity::all::{CommandOptionType, CreateCommand, CreateCommandOption, Interaction};
-use serenity::gateway::{ActivityData, ShardManager};
+use serenity::all::{
+    CommandOptionType, CreateCommand, CreateCommandOption, Guild, GuildChannel, Interaction,
+    Permissions, UnavailableGuild,
+};
+use serenity::gateway::{ActivityData, ShardStageUpdateEvent};
 use serenity::model::channel::Message;
 use serenity::model::gateway::Ready;
 use serenity::prelude::*;
 use serenity::{all::GuildId, async_trait};
+use tracing::{error, info, warn};
 
-use commands::{list_command, remove_command, setup_command};
-use configs::load_configs;
+use archive::init_archive;
+use cleanup::{channel_deleted, guild_removed};
+use commands::{
+    TEST_EMBED_BUTTON, admin_command, adminrole_command, config_command, explain_command,
+    kill_command, list_command, remove_command, setup_command, test_command, test_embed_button,
+};
+use configs::{export_configs, import_configs, load_configs, watch_configs};
+use dedup::init_dedup;
+use health::{set_cache_ready, shard_stage_changed};
 use helpers::get_most_expensive_recent_kill;
+use http::serve_http;
+use logging::init_logging;
+use metrics::init_metrics;
+use settings::Task;
+use sources::{KillSource, source_from_env};
+use supervisor::supervise;
 use ws::kill_feed;
 
-pub struct ShardManagerContainer;
-
-impl TypeMapKey for ShardManagerContainer {
-    type Value = Arc<ShardManager>;
-}
-
 struct Bot {
     is_loop_running: AtomicBool,
+    source: Mutex<Option<Box<dyn KillSource>>>,
 }
 
 #[async_trait]
 impl EventHandler for Bot {
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        if let Interaction::Command(command) = interaction {
-            match command.data.name.as_str() {
+        match interaction {
+            Interaction::Command(command) => match command.data.name.as_str() {
                 "setup" => setup_command(&ctx, &command).await,
                 "remove" => remove_command(&ctx, &command).await,
                 "list" => list_command(&ctx, &command).await,
+                "adminrole" => adminrole_command(&ctx, &command).await,
+                "test" => test_command(&ctx, &command).await,
+                "explain" => explain_command(&ctx, &command).await,
+                "kill" => kill_command(&ctx, &command).await,
+                "config" => config_command(&ctx, &command).await,
+                "admin" => admin_command(&ctx, &command).await,
                 _ => {}
+            },
+            Interaction::Component(component) if component.data.custom_id == TEST_EMBED_BUTTON => {
+                test_embed_button(&ctx, &component).await
             }
+            _ => {}
+        }
+    }
+
+    async fn guild_delete(
+        &self,
+        _ctx: Context,
+        incomplete: UnavailableGuild,
+        _full: Option<Guild>,
+    ) {
+        // An unavailable guild is an outage, not the bot being removed.
+        if !incomplete.unavailable {
+            guild_removed(incomplete.id.get()).await;
         }
     }
 
+    async fn channel_delete(
+        &self,
+        ctx: Context,
+        channel: GuildChannel,
+        _messages: Option<Vec<Message>>,
+    ) {
+        channel_deleted(&ctx.http, channel.id.get()).await;
+    }
+
+    async fn shard_stage_update(&self, _ctx: Context, event: ShardStageUpdateEvent) {
+        shard_stage_changed(&event);
+    }
+
     async fn message(&self, ctx: Context, msg: Message) {
-        if msg.content == "!hello" {
-            if let Err(e) = msg.channel_id.say(&ctx.http, "world!").await {
-                error!("Error sending message: {:?}", e);
-            }
+        if msg.content == "!hello"
+            && let Err(e) = msg.channel_id.say(&ctx.http, "world!").await
+        {
+            error!("Error sending message: {:?}", e);
         }
     }
 
@@ -60,6 +119,7 @@ impl EventHandler for Bot {
 
         let setup_command = CreateCommand::new("setup")
             .description("Setup killmail tracking for this server")
+            .default_member_permissions(Permissions::MANAGE_GUILD)
             .add_option(
                 CreateCommandOption::new(
                     CommandOptionType::String,
@@ -71,6 +131,7 @@ impl EventHandler for Bot {
 
         let remove_command = CreateCommand::new("remove")
             .description("Remove an ID from tracking")
+            .default_member_permissions(Permissions::MANAGE_GUILD)
             .add_option(
                 CreateCommandOption::new(
                     CommandOptionType::String,
@@ -82,7 +143,97 @@ impl EventHandler for Bot {
 
         let list_command = CreateCommand::new("list").description("Show all currently tracked IDs");
 
-        for command in [setup_command, remove_command, list_command] {
+        let adminrole_command = CreateCommand::new("adminrole")
+            .description("Let members of a role change tracking without Manage Server")
+            .default_member_permissions(Permissions::MANAGE_GUILD)
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Role,
+                "role",
+                "Bot admin role, leave empty to clear it",
+            ));
+
+        let test_command = CreateCommand::new("test")
+            .description("Post the latest killmail to the configured channel and explain the match")
+            .default_member_permissions(Permissions::MANAGE_GUILD);
+
+        let explain_command = CreateCommand::new("explain")
+            .description("Explain why a killmail was or wasn't posted here")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "killmail",
+                    "Killmail ID or zKillboard link",
+                )
+                .required(true),
+            );
+
+        let kill_command = CreateCommand::new("kill")
+            .description("Post any killmail by ID or zKillboard link")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "killmail",
+                    "Killmail ID or zKillboard link",
+                )
+                .required(true),
+            );
+
+        let config_command = CreateCommand::new("config")
+            .description("Show, export or import this server's configuration")
+            .default_member_permissions(Permissions::MANAGE_GUILD)
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "show",
+                "Show every setting, with names for the tracked IDs",
+            ))
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "export",
+                    "Download the configuration as a file",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "format", "File format")
+                        .add_string_choice("TOML", "toml")
+                        .add_string_choice("JSON", "json"),
+                ),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "import",
+                    "Replace the configuration with an exported file",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Attachment,
+                        "file",
+                        "File from /config export",
+                    )
+                    .required(true),
+                ),
+            );
+
+        let admin_command = CreateCommand::new("admin")
+            .description("Bot owner tools")
+            .default_member_permissions(Permissions::ADMINISTRATOR)
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "reload",
+                "Reload server configurations from disk",
+            ));
+
+        for command in [
+            setup_command,
+            remove_command,
+            list_command,
+            adminrole_command,
+            test_command,
+            explain_command,
+            kill_command,
+            config_command,
+            admin_command,
+        ] {
             if let Err(why) = ctx.http.create_global_command(&command).await {
                 error!("cannot create slash command: {}", why);
             }
@@ -91,22 +242,45 @@ impl EventHandler for Bot {
 
     async fn cache_ready(&self, ctx: Context, _guilds: Vec<GuildId>) {
         info!("Cache built succesfully!");
+        set_cache_ready();
 
         let ctx = Arc::new(ctx);
 
-        if !self.is_loop_running.load(Ordering::Relaxed) {
-            let ctx1 = Arc::clone(&ctx);
-            tokio::spawn(async move {
-                kill_feed(&ctx1).await;
-            });
+        if !self.is_loop_running.load(Ordering::Relaxed)
+            && let Some(source) = self.source.lock().await.take()
+        {
+            if source.restarts() {
+                let ctx1 = Arc::clone(&ctx);
+                let mut source = Some(source);
+                tokio::spawn(supervise("kill feed", move || {
+                    let ctx = Arc::clone(&ctx1);
+                    // The first run uses the source checked at startup, later
+                    // ones start from scratch.
+                    let source = source.take().map(Ok).unwrap_or_else(source_from_env);
+                    async move {
+                        match source {
+                            Ok(source) => kill_feed(&ctx, source).await,
+                            Err(e) => error!("invalid kill feed configuration: {}", e),
+                        }
+                    }
+                }));
+            } else {
+                let ctx1 = Arc::clone(&ctx);
+                tokio::spawn(async move {
+                    kill_feed(&ctx1, source).await;
+                });
+            }
 
             let ctx2 = Arc::clone(&ctx);
-            tokio::spawn(async move {
-                loop {
-                    set_activity_to_expensive_kill(&ctx2).await;
-                    tokio::time::sleep(Duration::from_secs(60)).await;
+            tokio::spawn(supervise("activity", move || {
+                let ctx = Arc::clone(&ctx2);
+                async move {
+                    loop {
+                        set_activity_to_expensive_kill(&ctx).await;
+                        tokio::time::sleep(Duration::from_secs(60)).await;
+                    }
                 }
-            });
+            }));
 
             self.is_loop_running.swap(true, Ordering::Relaxed);
         }
@@ -137,12 +311,67 @@ async fn set_activity_to_expensive_kill(ctx: &Context) {
     }
 }
 
+/// Runs a job asked for on the command line and exits.
+fn run_task(task: Task) -> ! {
+    let result = match &task {
+        Task::ImportConfigs(path) => import_configs(path)
+            .map(|count| info!("imported {} server configurations from {:?}", count, path)),
+        Task::ExportConfigs(path) => export_configs(path)
+            .map(|count| info!("exported {} server configurations to {:?}", count, path)),
+    };
+
+    match result {
+        Ok(()) => std::process::exit(0),
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
-    colog::init();
-    let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
+    if let Err(e) = settings::init_settings() {
+        eprintln!("invalid settings: {}", e);
+        std::process::exit(1);
+    }
 
-    load_configs().await;
+    if let Err(e) = init_logging() {
+        eprintln!("cannot set up logging: {}", e);
+        std::process::exit(1);
+    }
+    info!("using data directory {:?}", settings::data_dir());
+
+    if let Some(task) = settings::task() {
+        run_task(task);
+    }
+
+    init_metrics();
+    tokio::spawn(serve_http());
+
+    let token = match settings::discord_token() {
+        Ok(token) => token,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let source = match source_from_env() {
+        Ok(source) => source,
+        Err(e) => {
+            error!("invalid kill feed configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = load_configs().await {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+    tokio::spawn(watch_configs());
+    init_archive();
+    init_dedup().await;
 
     let intents = GatewayIntents::GUILD_MESSAGES
         | GatewayIntents::DIRECT_MESSAGES
@@ -152,14 +381,24 @@ async fn main() {
     let mut client = Client::builder(&token, intents)
         .event_handler(Bot {
             is_loop_running: AtomicBool::new(false),
+            source: Mutex::new(Some(source)),
         })
         .await
         .expect("Error creating client");
 
-    {
-        let mut data = client.data.write().await;
-        data.insert::<ShardManagerContainer>(client.shard_manager.clone())
-    }
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        shutdown::wait_for_signal().await;
+
+        // A second signal skips the rest of the cleanup.
+        tokio::select! {
+            _ = shutdown::shutdown(shard_manager) => std::process::exit(0),
+            _ = shutdown::wait_for_signal() => {
+                warn!("second signal received, exiting immediately");
+                std::process::exit(1);
+            }
+        }
+    });
 
     if let Err(why) = client.start().await {
         error!("Client error: {why:?}");
@@ -0,0 +1,203 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use tracing::error;
+
+use crate::ws::SERVER_CONFIGS;
+
+pub static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+pub static KILLMAILS_RECEIVED: Lazy<IntCounter> = Lazy::new(|| {
+    register(IntCounter::new(
+        "draugur_killmails_received_total",
+        "Killmails delivered by the kill source, including duplicates",
+    ))
+});
+
+pub static KILLMAILS_DUPLICATE: Lazy<IntCounter> = Lazy::new(|| {
+    register(IntCounter::new(
+        "draugur_killmails_duplicate_total",
+        "Killmails skipped because they were seen before",
+    ))
+});
+
+pub static KILLMAILS_MATCHED: Lazy<IntCounter> = Lazy::new(|| {
+    register(IntCounter::new(
+        "draugur_killmails_matched_total",
+        "Killmails that matched the follows of at least one server",
+    ))
+});
+
+pub static KILLMAIL_TASK_PANICS: Lazy<IntCounter> = Lazy::new(|| {
+    register(IntCounter::new(
+        "draugur_killmail_task_panics_total",
+        "Killmails whose handling panicked and were skipped",
+    ))
+});
+
+pub static FEED_LAG: Lazy<Histogram> = Lazy::new(|| {
+    register(Histogram::with_opts(
+        HistogramOpts::new(
+            "draugur_feed_lag_seconds",
+            "Time between a kill happening and the bot receiving it",
+        )
+        .buckets(vec![
+            5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0,
+        ]),
+    ))
+});
+
+pub static REDISQ_POLL_DURATION: Lazy<Histogram> = Lazy::new(|| {
+    register(Histogram::with_opts(
+        HistogramOpts::new(
+            "draugur_redisq_poll_duration_seconds",
+            "How long RedisQ polls take, including waiting for a killmail",
+        )
+        .buckets(vec![0.1, 0.5, 1.0, 2.0, 5.0, 10.0, 15.0, 30.0]),
+    ))
+});
+
+pub static ESI_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "draugur_esi_requests_total",
+            "Requests made to ESI, by endpoint",
+        ),
+        &["endpoint"],
+    ))
+});
+
+pub static ESI_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "draugur_esi_errors_total",
+            "Failed ESI requests, by endpoint",
+        ),
+        &["endpoint"],
+    ))
+});
+
+pub static ESI_REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register(HistogramVec::new(
+        HistogramOpts::new(
+            "draugur_esi_request_duration_seconds",
+            "How long ESI requests take, by endpoint",
+        ),
+        &["endpoint"],
+    ))
+});
+
+pub static ESI_CACHE_LOOKUPS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "draugur_esi_cache_lookups_total",
+            "Lookups in the ESI universe cache, by cache and hit or miss",
+        ),
+        &["cache", "result"],
+    ))
+});
+
+pub static CONFIGURED_GUILDS: Lazy<IntGauge> = Lazy::new(|| {
+    register(IntGauge::new(
+        "draugur_configured_guilds",
+        "Servers with a configuration",
+    ))
+});
+
+pub static CONFIGURED_FOLLOWS: Lazy<IntGauge> = Lazy::new(|| {
+    register(IntGauge::new(
+        "draugur_configured_follows",
+        "Follow IDs across all servers",
+    ))
+});
+
+pub static EMBEDS_POSTED: Lazy<IntCounter> = Lazy::new(|| {
+    register(IntCounter::new(
+        "draugur_embeds_posted_total",
+        "Killmail embeds successfully posted to Discord",
+    ))
+});
+
+pub static DISCORD_SEND_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "draugur_discord_send_failures_total",
+            "Failed attempts to post a message to Discord, by reason",
+        ),
+        &["reason"],
+    ))
+});
+
+pub static OUTBOUND_DROPPED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "draugur_outbound_dropped_total",
+            "Killmail embeds that were never delivered, by reason",
+        ),
+        &["reason"],
+    ))
+});
+
+pub static TASK_RESTARTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "draugur_task_restarts_total",
+            "Background tasks restarted after they panicked or exited, by task",
+        ),
+        &["task"],
+    ))
+});
+
+/// Registers every metric up front, so all of them show up in the first
+/// scrape rather than once they're first used.
+pub fn init_metrics() {
+    Lazy::force(&EMBEDS_POSTED);
+    Lazy::force(&DISCORD_SEND_FAILURES);
+    Lazy::force(&OUTBOUND_DROPPED);
+    Lazy::force(&KILLMAILS_RECEIVED);
+    Lazy::force(&KILLMAILS_DUPLICATE);
+    Lazy::force(&KILLMAILS_MATCHED);
+    Lazy::force(&KILLMAIL_TASK_PANICS);
+    Lazy::force(&FEED_LAG);
+    Lazy::force(&REDISQ_POLL_DURATION);
+    Lazy::force(&ESI_REQUESTS);
+    Lazy::force(&ESI_ERRORS);
+    Lazy::force(&ESI_REQUEST_DURATION);
+    Lazy::force(&ESI_CACHE_LOOKUPS);
+    Lazy::force(&CONFIGURED_GUILDS);
+    Lazy::force(&CONFIGURED_FOLLOWS);
+    Lazy::force(&TASK_RESTARTS);
+}
+
+/// Renders every metric in the Prometheus text format.
+pub async fn render_metrics() -> String {
+    {
+        let configs = SERVER_CONFIGS.read().await;
+        CONFIGURED_GUILDS.set(configs.len() as i64);
+        CONFIGURED_FOLLOWS.set(
+            configs
+                .values()
+                .map(|config| config.follow_ids.len() as i64)
+                .sum(),
+        );
+    }
+
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer) {
+        error!("cannot encode metrics: {}", e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+fn register<T>(metric: prometheus::Result<T>) -> T
+where
+    T: prometheus::core::Collector + Clone + 'static,
+{
+    let metric = metric.expect("invalid metric definition");
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("metric registered twice");
+    metric
+}
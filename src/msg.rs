@@ -1,16 +1,20 @@
-use serenity::all::{ChannelId, CreateEmbedAuthor};
-use serenity::builder::{CreateEmbed, CreateEmbedFooter, CreateMessage};
+use serenity::all::CreateEmbedAuthor;
+use serenity::builder::{CreateEmbed, CreateEmbedFooter};
 use serenity::client::Context;
 use serenity::model::Colour;
 
-use crate::esi;
-use crate::helpers::{format_isk, format_time, get_final_blower};
-use crate::models::{Killmail, Zkb};
+use crate::enrich::EnrichedKill;
+use crate::helpers::{format_isk, format_time};
+use crate::outbound::enqueue;
 
 const GREEN_KILL: Colour = Colour::from_rgb(50, 230, 175);
 const RED_LOSS: Colour = Colour::from_rgb(180, 50, 110);
 
-pub async fn create_msg(ctx: &Context, channel_id: u64, kill_type: String, km: Killmail, zkb: Zkb) {
+/// Renders the embed for a killmail, as a kill or a loss depending on
+/// `kill_type`. All names come from `kill`, so this never touches ESI.
+pub fn create_embed(kill: &EnrichedKill, kill_type: &str) -> CreateEmbed {
+    let km = &kill.killmail;
+
     let color = if kill_type == "loss" {
         RED_LOSS
     } else {
@@ -29,8 +33,7 @@ pub async fn create_msg(ctx: &Context, channel_id: u64, kill_type: String, km: K
             "https://images.evetech.net/alliances/{}/logo?size=64",
             km.victim.alliance_id.unwrap_or(0)
         )
-    }
-    .to_string();
+    };
 
     let ship_badge = format!(
         "https://images.evetech.net/types/{}/render?size=64",
@@ -38,98 +41,48 @@ pub async fn create_msg(ctx: &Context, channel_id: u64, kill_type: String, km: K
     );
     let url = format!("https://zkillboard.com/kill/{}/", km.killmail_id);
 
-    let fmt_time = format_time(km.killmail_time);
+    let fmt_time = format_time(km.killmail_time.clone());
 
-    let footer_str = format!("{} • {}", format_isk(zkb.total_value), fmt_time);
+    let footer_str = format!("{} • {}", format_isk(kill.value.total), fmt_time);
     let footer = CreateEmbedFooter::new(footer_str);
 
     let author = CreateEmbedAuthor::new(author).icon_url(badge_url).url(&url);
 
-    let vic = esi::Character::get_character(km.victim.character_id).await;
-    let vic_result_name = match vic {
-        Ok(vic_name) => vic_name.name,
-        _ => "Unknown".into(),
-    };
-
-    let vic_ship = esi::Ship::get_ship(km.victim.ship_type_id).await;
-    let vic_ship_name = match vic_ship {
-        Ok(shp) => shp.name,
-        _ => "Unknown".into(),
-    };
-
-    let system = esi::System::get_system(km.solar_system_id).await;
-    let system_name = match system {
-        Ok(ref sys) => &sys.name,
-        _ => "Unknown",
-    };
-
-    let const_id = match system {
-        Ok(ref const_id) => const_id.constellation_id,
-        _ => 0,
-    };
-
-    let constellation = esi::Constellation::get_constellation(const_id.to_owned()).await;
-    let region = match constellation {
-        Ok(constel) => constel.region_id,
-        _ => 0,
-    };
-
-    let region = esi::Region::get_region(region).await;
-    let region_name = match region {
-        Ok(reg) => reg.name,
-        _ => "Unknown".into(),
-    };
-
-    let solo = if zkb.solo == true || km.attackers.len() == 1 {
+    let solo = if kill.zkb.solo || km.attackers.len() == 1 {
         "solo!".to_string()
     } else if km.attackers.len() == 2 {
-        format!("with 1 friend")
+        "with 1 friend".to_string()
     } else {
         format!("with {} friends", km.attackers.len() - 1)
     };
 
-    let (fb, fb_id, fb_ship_name, fb_corp_id) = get_final_blower(&km.attackers).await;
-    let fb_corp = esi::Corporation::get_corp(fb_corp_id).await;
-    let fb_corp_name = match fb_corp {
-        Ok(fcn) => fcn.name,
-        _ => "Unknown".into(),
-    };
-
-    let vic_corp = esi::Corporation::get_corp(km.victim.corporation_id).await;
-    let vic_corp_name = match vic_corp {
-        Ok(vcn) => vcn.name,
-        _ => "Unknown".into(),
-    };
+    let fb = &kill.final_blow;
 
-    let embed = CreateEmbed::new()
+    CreateEmbed::new()
         .colour(color)
         .title(format!(
             "{}'s {} was destroyed in {} ({})",
-            vic_result_name, vic_ship_name, system_name, region_name
+            kill.victim_name, kill.victim_ship_name, kill.system_name, kill.region_name
         ))
         .url(&url)
         .author(author)
         .description(format!(
             "**{} ({})** lost their {} to **[{}](https://zkillboard.com/character/{}/) ({})** flying a {} {}",
-            vic_result_name,
-            vic_corp_name,
-            vic_ship_name,
-            fb,
-            fb_id,
-            fb_corp_name,
-            fb_ship_name,
+            kill.victim_name,
+            kill.victim_corp_name,
+            kill.victim_ship_name,
+            fb.name,
+            fb.character_id,
+            fb.corporation_name,
+            fb.ship_name,
             solo
         ))
         .thumbnail(ship_badge)
-        .footer(footer);
-
-    let builder = CreateMessage::new().embed(embed);
-
-    let msg = ChannelId::new(channel_id)
-        .send_message(&ctx.http, builder)
-        .await;
+        .footer(footer)
+}
 
-    if let Err(why) = msg {
-        println!("Error sending message: {why:?}")
-    }
+/// Queues the embed for `kill` on the channel's outbound queue, which takes
+/// care of rate limits, retries and reporting anything that gets dropped.
+pub fn send_msg(ctx: &Context, channel_id: u64, kill: &EnrichedKill, kill_type: &str) {
+    enqueue(&ctx.http, channel_id, create_embed(kill, kill_type));
 }
@@ -0,0 +1,232 @@
+use once_cell::sync::Lazy;
+use serenity::all::{ChannelId, CreateEmbed, CreateMessage, Http};
+use serenity::http::HttpError;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{Semaphore, mpsc};
+use tracing::{Instrument, Span, debug, error, info, info_span, warn};
+
+use crate::cleanup::{channel_failed, channel_succeeded};
+use crate::metrics::{DISCORD_SEND_FAILURES, EMBEDS_POSTED, OUTBOUND_DROPPED};
+use crate::settings;
+
+/// Embeds waiting per channel before new ones are dropped.
+const CHANNEL_QUEUE_SIZE: usize = 200;
+/// Discord accepts up to 10 embeds in a single message.
+const MAX_EMBEDS_PER_MESSAGE: usize = 10;
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Channel workers shut down after this long without anything to send.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+const DEFAULT_DELIVERY_CONCURRENCY: usize = 8;
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Caps how many messages are being delivered to Discord at once across all
+/// channels, set with `DRAUGUR_DELIVERY_CONCURRENCY`.
+static DELIVERY_PERMITS: Lazy<Semaphore> = Lazy::new(|| {
+    let permits = settings::var("DRAUGUR_DELIVERY_CONCURRENCY")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|permits| *permits > 0)
+        .unwrap_or(DEFAULT_DELIVERY_CONCURRENCY);
+    Semaphore::new(permits)
+});
+
+static QUEUES: Lazy<Mutex<HashMap<u64, mpsc::Sender<Queued>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Embeds queued or being sent, across all channels.
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// An embed waiting for delivery, with the span it was queued in so the
+/// outcome is logged against the killmail and guild it belongs to.
+struct Queued {
+    embed: CreateEmbed,
+    span: Span,
+}
+
+/// What went wrong when posting to a channel.
+enum SendFailure {
+    /// Worth retrying: rate limits, Discord server errors, network trouble.
+    Transient(&'static str),
+    /// Retrying won't help, e.g. missing permissions or a deleted channel.
+    Permanent(&'static str),
+}
+
+/// Queues an embed for delivery to `channel_id`. Every channel has its own
+/// worker, so a slow or rate limited channel never holds up the others.
+pub fn enqueue(http: &Arc<Http>, channel_id: u64, embed: CreateEmbed) {
+    let Ok(mut queues) = QUEUES.lock() else {
+        OUTBOUND_DROPPED.with_label_values(&["poisoned"]).inc();
+        return;
+    };
+
+    let queued = Queued {
+        embed,
+        span: Span::current(),
+    };
+    let queued = match queues.get(&channel_id) {
+        Some(tx) => match tx.try_send(queued) {
+            Ok(()) => {
+                PENDING.fetch_add(1, Ordering::SeqCst);
+                return;
+            }
+            Err(TrySendError::Full(_)) => {
+                warn!("outbound queue for channel {} is full", channel_id);
+                OUTBOUND_DROPPED.with_label_values(&["queue_full"]).inc();
+                return;
+            }
+            Err(TrySendError::Closed(queued)) => queued,
+        },
+        None => queued,
+    };
+
+    let (tx, rx) = mpsc::channel(CHANNEL_QUEUE_SIZE);
+    if tx.try_send(queued).is_ok() {
+        PENDING.fetch_add(1, Ordering::SeqCst);
+    }
+    queues.insert(channel_id, tx);
+    tokio::spawn(channel_worker(Arc::clone(http), channel_id, rx));
+}
+
+async fn channel_worker(http: Arc<Http>, channel_id: u64, mut rx: mpsc::Receiver<Queued>) {
+    loop {
+        let first = match tokio::time::timeout(IDLE_TIMEOUT, rx.recv()).await {
+            Ok(Some(queued)) => queued,
+            Ok(None) => return,
+            Err(_) => {
+                // Only retire the worker if nothing was queued in the meantime.
+                if let Ok(mut queues) = QUEUES.lock()
+                    && rx.is_empty()
+                {
+                    queues.remove(&channel_id);
+                    return;
+                }
+                continue;
+            }
+        };
+
+        // Coalesce a burst into as few messages as possible.
+        let mut batch = vec![first];
+        while batch.len() < MAX_EMBEDS_PER_MESSAGE {
+            match rx.try_recv() {
+                Ok(queued) => batch.push(queued),
+                Err(_) => break,
+            }
+        }
+
+        let count = batch.len();
+        let span = info_span!("send", channel = channel_id, embeds = count);
+        for queued in &batch {
+            span.follows_from(&queued.span);
+        }
+        let embeds = batch.iter().map(|queued| queued.embed.clone()).collect();
+        let posted = deliver(&http, channel_id, embeds).instrument(span).await;
+        for queued in &batch {
+            queued.span.in_scope(|| debug!(posted, "delivery finished"));
+        }
+        PENDING.fetch_sub(count, Ordering::SeqCst);
+    }
+}
+
+/// Waits for everything queued to be delivered, or dropped after its
+/// retries, giving up after `timeout`.
+pub async fn drain_outbound(timeout: Duration) {
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    loop {
+        let pending = PENDING.load(Ordering::SeqCst);
+        if pending == 0 {
+            info!("outbound queues drained");
+            return;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            warn!(
+                "{} embed(s) still queued after {:?}, dropping them",
+                pending, timeout
+            );
+            OUTBOUND_DROPPED
+                .with_label_values(&["shutdown"])
+                .inc_by(pending as u64);
+            return;
+        }
+        tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+    }
+}
+
+/// Posts `embeds` as a single message, returning whether it went through.
+async fn deliver(http: &Http, channel_id: u64, embeds: Vec<CreateEmbed>) -> bool {
+    let count = embeds.len() as u64;
+    let mut backoff = INITIAL_BACKOFF;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let builder = CreateMessage::new().embeds(embeds.clone());
+        let result = {
+            let Ok(_permit) = DELIVERY_PERMITS.acquire().await else {
+                return false;
+            };
+            ChannelId::new(channel_id).send_message(http, builder).await
+        };
+
+        let why = match result {
+            Ok(_) => {
+                EMBEDS_POSTED.inc_by(count);
+                channel_succeeded(channel_id);
+                return true;
+            }
+            Err(why) => why,
+        };
+
+        match classify(&why) {
+            SendFailure::Transient(reason) => {
+                DISCORD_SEND_FAILURES.with_label_values(&[reason]).inc();
+                warn!(
+                    "sending to channel {} failed (attempt {}/{}): {}",
+                    channel_id, attempt, MAX_ATTEMPTS, why
+                );
+                if attempt < MAX_ATTEMPTS {
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+            }
+            SendFailure::Permanent(reason) => {
+                DISCORD_SEND_FAILURES.with_label_values(&[reason]).inc();
+                OUTBOUND_DROPPED.with_label_values(&[reason]).inc_by(count);
+                error!("cannot send to channel {}: {}", channel_id, why);
+                if reason == "forbidden" || reason == "not_found" {
+                    channel_failed(http, channel_id).await;
+                }
+                return false;
+            }
+        }
+    }
+
+    error!(
+        "giving up on {} embed(s) for channel {} after {} attempts",
+        count, channel_id, MAX_ATTEMPTS
+    );
+    OUTBOUND_DROPPED
+        .with_label_values(&["retries_exhausted"])
+        .inc_by(count);
+    false
+}
+
+fn classify(why: &serenity::Error) -> SendFailure {
+    match why {
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(response)) => {
+            match response.status_code.as_u16() {
+                429 => SendFailure::Transient("rate_limited"),
+                403 => SendFailure::Permanent("forbidden"),
+                404 => SendFailure::Permanent("not_found"),
+                status if status >= 500 => SendFailure::Transient("server_error"),
+                _ => SendFailure::Permanent("rejected"),
+            }
+        }
+        serenity::Error::Http(HttpError::Request(_)) => SendFailure::Transient("network"),
+        _ => SendFailure::Permanent("other"),
+    }
+}
@@ -0,0 +1,201 @@
+use once_cell::sync::OnceCell;
+use std::collections::HashMap;
+use std::env::{self, VarError};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Bot-level settings file, read from the data directory.
+pub const SETTINGS_FILE: &str = "draugur.toml";
+
+/// Everything `draugur.toml` may set. Each key is the environment variable
+/// name in lower case without the `DRAUGUR_` prefix, e.g. `queue_id` for
+/// `DRAUGUR_QUEUE_ID`, and `discord_token` for `DISCORD_TOKEN`.
+const KNOWN_SETTINGS: &[&str] = &[
+    "DISCORD_TOKEN",
+    "DRAUGUR_DISCORD_TOKEN_FILE",
+    "DRAUGUR_LOG_LEVEL",
+    "DRAUGUR_LOG_FORMAT",
+    "DRAUGUR_SOURCE",
+    "DRAUGUR_QUEUE_ID",
+    "DRAUGUR_REDISQ_TTW",
+    "DRAUGUR_WS_CHANNELS",
+    "DRAUGUR_CONFIG_STORE",
+    "DRAUGUR_CONFIG_PATH",
+    "DRAUGUR_CONFIG_STRICT",
+    "DRAUGUR_DELIVERY_CONCURRENCY",
+    "DRAUGUR_HTTP_ADDR",
+    "DRAUGUR_FEED_TIMEOUT_MINUTES",
+    "DRAUGUR_DEDUP_WINDOW_MINUTES",
+    "DRAUGUR_DEDUP_CAPACITY",
+    "DRAUGUR_DEDUP_FILE",
+    "DRAUGUR_ARCHIVE",
+    "DRAUGUR_ARCHIVE_PATH",
+    "DRAUGUR_ARCHIVE_TRACKED_ONLY",
+    "DRAUGUR_REPLAY",
+    "DRAUGUR_REPLAY_SPEED",
+    "DRAUGUR_SHUTDOWN_TIMEOUT",
+];
+
+/// A one-off job asked for on the command line, run instead of the bot.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Task {
+    /// `--import-configs <file>`: replace the stored server configurations
+    /// with a `server_configs.toml` file.
+    ImportConfigs(PathBuf),
+    /// `--export-configs <file>`: write every stored server configuration to
+    /// a `server_configs.toml` file.
+    ExportConfigs(PathBuf),
+}
+
+struct Settings {
+    data_dir: PathBuf,
+    task: Option<Task>,
+    /// Values from `draugur.toml`, keyed by environment variable name.
+    file: HashMap<String, String>,
+}
+
+static SETTINGS: OnceCell<Settings> = OnceCell::new();
+
+/// Picks the data directory (`--data-dir`, then `DRAUGUR_DATA_DIR`, then the
+/// working directory) and reads `draugur.toml` from it. This runs before
+/// logging is set up, so problems are returned rather than logged.
+pub fn init_settings() -> Result<(), String> {
+    let (data_dir, task) = parse_args(env::args().skip(1))?;
+    let data_dir = match data_dir {
+        Some(data_dir) => data_dir,
+        None => env::var("DRAUGUR_DATA_DIR")
+            .ok()
+            .filter(|data_dir| !data_dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(".")),
+    };
+
+    fs::create_dir_all(&data_dir)
+        .map_err(|e| format!("cannot create data directory {:?}: {}", data_dir, e))?;
+    let file = read_settings_file(&data_dir.join(SETTINGS_FILE))?;
+
+    let _ = SETTINGS.set(Settings {
+        data_dir,
+        task,
+        file,
+    });
+    Ok(())
+}
+
+fn parse_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<(Option<PathBuf>, Option<Task>), String> {
+    let mut data_dir = None;
+    let mut task = None;
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .map(PathBuf::from)
+                .ok_or_else(|| format!("{} needs a path", name))
+        };
+
+        match name.as_str() {
+            "--data-dir" => data_dir = Some(value()?),
+            "--import-configs" => task = Some(Task::ImportConfigs(value()?)),
+            "--export-configs" => task = Some(Task::ExportConfigs(value()?)),
+            _ => return Err(format!("unknown argument '{}'", name)),
+        }
+    }
+
+    Ok((data_dir, task))
+}
+
+fn read_settings_file(path: &Path) -> Result<HashMap<String, String>, String> {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(format!("cannot read {:?}: {}", path, e)),
+    };
+
+    let table = data
+        .parse::<toml::Table>()
+        .map_err(|e| format!("cannot parse {:?}: {}", path, e))?;
+
+    let mut settings = HashMap::new();
+    for (key, value) in table {
+        let name = match key.as_str() {
+            "discord_token" => "DISCORD_TOKEN".to_string(),
+            _ => format!("DRAUGUR_{}", key.to_uppercase()),
+        };
+        if !KNOWN_SETTINGS.contains(&name.as_str()) {
+            return Err(format!("unknown setting '{}' in {:?}", key, path));
+        }
+
+        let value = match value {
+            toml::Value::String(value) => value,
+            toml::Value::Integer(value) => value.to_string(),
+            toml::Value::Float(value) => value.to_string(),
+            toml::Value::Boolean(value) => value.to_string(),
+            _ => {
+                return Err(format!(
+                    "setting '{}' in {:?} must be a string, number or boolean",
+                    key, path
+                ));
+            }
+        };
+        settings.insert(name, value);
+    }
+
+    Ok(settings)
+}
+
+/// Looks a setting up in the environment first and `draugur.toml` second,
+/// with the same signature as `std::env::var`.
+pub fn var(name: &str) -> Result<String, VarError> {
+    match env::var(name) {
+        Err(VarError::NotPresent) => SETTINGS
+            .get()
+            .and_then(|settings| settings.file.get(name).cloned())
+            .ok_or(VarError::NotPresent),
+        result => result,
+    }
+}
+
+/// The job asked for on the command line, if the bot shouldn't run.
+pub fn task() -> Option<Task> {
+    SETTINGS.get().and_then(|settings| settings.task.clone())
+}
+
+/// The directory holding the configuration store, archives and caches.
+pub fn data_dir() -> PathBuf {
+    SETTINGS
+        .get()
+        .map(|settings| settings.data_dir.clone())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Resolves `path` against the data directory unless it is absolute.
+pub fn data_path(path: impl AsRef<Path>) -> PathBuf {
+    data_dir().join(path)
+}
+
+/// The Discord bot token, from `DISCORD_TOKEN` or the file named by
+/// `DRAUGUR_DISCORD_TOKEN_FILE`, e.g. a Docker secret.
+pub fn discord_token() -> Result<String, String> {
+    if let Ok(token) = var("DISCORD_TOKEN")
+        && !token.is_empty()
+    {
+        return Ok(token);
+    }
+
+    match var("DRAUGUR_DISCORD_TOKEN_FILE") {
+        Ok(path) => fs::read_to_string(data_path(&path))
+            .map(|token| token.trim().to_string())
+            .map_err(|e| format!("cannot read Discord token from {:?}: {}", path, e)),
+        Err(_) => {
+            Err("no Discord token, set DISCORD_TOKEN or DRAUGUR_DISCORD_TOKEN_FILE".to_string())
+        }
+    }
+}
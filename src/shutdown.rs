@@ -0,0 +1,90 @@
+use once_cell::sync::Lazy;
+use serenity::gateway::ShardManager;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::archive::flush_archive;
+use crate::configs::flush_configs;
+use crate::dedup::save_dedup;
+use crate::outbound::drain_outbound;
+use crate::settings;
+
+const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 15;
+/// How long the kill feed gets to finish the killmails it is working on.
+const FEED_TIMEOUT: Duration = Duration::from_secs(20);
+const ARCHIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+static SHUTDOWN: Lazy<watch::Sender<bool>> = Lazy::new(|| watch::channel(false).0);
+static FEED_RUNNING: Lazy<watch::Sender<bool>> = Lazy::new(|| watch::channel(false).0);
+
+/// Whether a shutdown has started. Kill sources check this before fetching
+/// more killmails.
+pub fn is_shutting_down() -> bool {
+    *SHUTDOWN.borrow()
+}
+
+/// Resolves once a shutdown has started.
+pub async fn shutdown_requested() {
+    let mut rx = SHUTDOWN.subscribe();
+    let _ = rx.wait_for(|shutting_down| *shutting_down).await;
+}
+
+/// Marks the kill feed as running or finished, so shutdown can wait for it.
+pub fn set_feed_running(running: bool) {
+    FEED_RUNNING.send_replace(running);
+}
+
+/// Waits for SIGINT or, on Unix, SIGTERM.
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(e) => {
+                warn!("cannot listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Stops the bot in order: the kill feed first, then whatever is still
+/// queued for Discord, then everything kept on disk, and finally the
+/// gateway connections.
+pub async fn shutdown(shard_manager: Arc<ShardManager>) {
+    info!("shutting down");
+    SHUTDOWN.send_replace(true);
+
+    let mut feed = FEED_RUNNING.subscribe();
+    if tokio::time::timeout(FEED_TIMEOUT, feed.wait_for(|running| !*running))
+        .await
+        .is_err()
+    {
+        warn!("kill feed didn't stop within {:?}", FEED_TIMEOUT);
+    }
+
+    let drain_timeout = settings::var("DRAUGUR_SHUTDOWN_TIMEOUT")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_DRAIN_TIMEOUT_SECS);
+    drain_outbound(Duration::from_secs(drain_timeout)).await;
+
+    flush_configs().await;
+    save_dedup().await;
+    flush_archive(ARCHIVE_TIMEOUT).await;
+
+    shard_manager.shutdown_all().await;
+    info!("shutdown complete");
+}
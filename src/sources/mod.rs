@@ -0,0 +1,64 @@
+pub mod r2z2;
+pub mod redisq;
+pub mod replay;
+pub mod websocket;
+
+use serenity::async_trait;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::models::{Killmail, Zkb};
+use crate::settings;
+
+use r2z2::R2z2Source;
+use redisq::{RedisQSettings, RedisQSource};
+use replay::{ReplaySettings, ReplaySource};
+use websocket::WebsocketSource;
+
+/// A feed of killmails. Sources push every killmail they receive into `tx`
+/// and return once the feed is exhausted or the receiving side is gone.
+#[async_trait]
+pub trait KillSource: Send {
+    fn name(&self) -> &'static str;
+
+    /// Whether the feed should be started again with a new source when this
+    /// one returns or panics. Replays end on their own and are not restarted.
+    fn restarts(&self) -> bool {
+        true
+    }
+
+    async fn run(&mut self, tx: mpsc::Sender<(Killmail, Zkb)>);
+}
+
+/// Picks the kill source from the environment. A configured replay always
+/// wins, otherwise `DRAUGUR_SOURCE` selects between `redisq` (the default),
+/// `websocket` and `r2z2`.
+pub fn source_from_env() -> Result<Box<dyn KillSource>, String> {
+    if let Some(settings) = ReplaySettings::from_env() {
+        return Ok(Box::new(ReplaySource::new(settings)));
+    }
+
+    let source: Box<dyn KillSource> = match settings::var("DRAUGUR_SOURCE")
+        .unwrap_or_default()
+        .to_lowercase()
+        .as_str()
+    {
+        "" | "redisq" => Box::new(RedisQSource::new(RedisQSettings::from_env()?)),
+        "websocket" => Box::new(WebsocketSource::from_env()),
+        "r2z2" => Box::new(R2z2Source::new()),
+        other => return Err(format!("unknown DRAUGUR_SOURCE '{}'", other)),
+    };
+
+    Ok(source)
+}
+
+/// Parses the killmail and zkb halves of a RedisQ `package` object.
+pub fn parse_package_value(package: &serde_json::Value) -> Option<(Killmail, Zkb)> {
+    let killmail_data = package.get("killmail")?;
+    let zkb_data = package.get("zkb")?;
+    debug!("ZKB DATA: {:?}", zkb_data);
+
+    let parsed = serde_json::from_value::<Killmail>(killmail_data.clone()).ok()?;
+    let zkb = serde_json::from_value::<Zkb>(zkb_data.clone()).ok()?;
+    Some((parsed, zkb))
+}
@@ -0,0 +1,119 @@
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serenity::async_trait;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use super::KillSource;
+use crate::models::{Killmail, Zkb};
+use crate::shutdown::is_shutting_down;
+
+const R2Z2_BASE: &str = "https://r2z2.zkillboard.com/ephemeral/";
+
+/// How long to wait when the next sequence file hasn't been published yet.
+const NOT_READY_DELAY: Duration = Duration::from_secs(6);
+
+#[derive(Deserialize)]
+struct Sequence {
+    sequence: u64,
+}
+
+#[derive(Deserialize)]
+struct SequenceFile {
+    esi: Killmail,
+    zkb: Zkb,
+}
+
+/// Walks zKillboard's R2Z2 sequence files, one killmail per file.
+pub struct R2z2Source {
+    client: reqwest::Client,
+    sequence: Option<u64>,
+}
+
+impl R2z2Source {
+    pub fn new() -> R2z2Source {
+        R2z2Source {
+            client: reqwest::Client::new(),
+            sequence: None,
+        }
+    }
+
+    async fn latest_sequence(&self) -> Result<u64, reqwest::Error> {
+        let url = format!("{}sequence.json", R2Z2_BASE);
+        let response: Sequence = self.client.get(url).send().await?.json().await?;
+        Ok(response.sequence)
+    }
+}
+
+impl Default for R2z2Source {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl KillSource for R2z2Source {
+    fn name(&self) -> &'static str {
+        "r2z2"
+    }
+
+    async fn run(&mut self, tx: mpsc::Sender<(Killmail, Zkb)>) {
+        while !is_shutting_down() {
+            let sequence = match self.sequence {
+                Some(sequence) => sequence,
+                None => match self.latest_sequence().await {
+                    Ok(sequence) => {
+                        info!("starting r2z2 feed at sequence {}", sequence);
+                        self.sequence = Some(sequence);
+                        sequence
+                    }
+                    Err(e) => {
+                        error!("cannot fetch r2z2 sequence: {}", e);
+                        tokio::time::sleep(NOT_READY_DELAY).await;
+                        continue;
+                    }
+                },
+            };
+
+            let url = format!("{}{}.json", R2Z2_BASE, sequence);
+            let response = match self.client.get(&url).send().await {
+                Ok(response) => response,
+                Err(e) => {
+                    error!("request failed: {}", e);
+                    tokio::time::sleep(Duration::from_secs(3)).await;
+                    continue;
+                }
+            };
+
+            match response.status() {
+                StatusCode::NOT_FOUND => {
+                    tokio::time::sleep(NOT_READY_DELAY).await;
+                    continue;
+                }
+                StatusCode::TOO_MANY_REQUESTS => {
+                    warn!("r2z2 rate limited, backing off");
+                    tokio::time::sleep(NOT_READY_DELAY * 2).await;
+                    continue;
+                }
+                status if !status.is_success() => {
+                    error!("r2z2 returned {} for sequence {}", status, sequence);
+                    tokio::time::sleep(Duration::from_secs(3)).await;
+                    continue;
+                }
+                _ => {}
+            }
+
+            self.sequence = Some(sequence + 1);
+
+            match response.json::<SequenceFile>().await {
+                Ok(file) => {
+                    if tx.send((file.esi, file.zkb)).await.is_err() {
+                        return;
+                    }
+                }
+                Err(e) => warn!("skipping unreadable r2z2 sequence {}: {}", sequence, e),
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use reqwest::StatusCode;
+use serenity::async_trait;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+use super::{KillSource, parse_package_value};
+use crate::metrics::REDISQ_POLL_DURATION;
+use crate::models::{Killmail, Zkb};
+use crate::settings;
+use crate::shutdown::is_shutting_down;
+
+const REDISQ_URL: &str = "https://zkillredisq.stream/listen.php";
+const DEFAULT_QUEUE_ID: &str = "draugur";
+const DEFAULT_TTW: u64 = 10;
+
+#[derive(Clone, Debug)]
+pub struct RedisQSettings {
+    pub queue_id: String,
+    pub ttw: u64,
+}
+
+impl RedisQSettings {
+    /// Reads `DRAUGUR_QUEUE_ID` and `DRAUGUR_REDISQ_TTW` from the environment.
+    ///
+    /// Every process polling RedisQ needs its own queue ID, otherwise they
+    /// split the killmails between them.
+    pub fn from_env() -> Result<RedisQSettings, String> {
+        let queue_id = match settings::var("DRAUGUR_QUEUE_ID") {
+            Ok(queue_id) => queue_id.trim().to_string(),
+            Err(_) => {
+                warn!(
+                    "DRAUGUR_QUEUE_ID is not set, using the shared '{}' queue. \
+                     Self-hosted instances should set their own queue ID or they will miss killmails",
+                    DEFAULT_QUEUE_ID
+                );
+                DEFAULT_QUEUE_ID.to_string()
+            }
+        };
+        validate_queue_id(&queue_id)?;
+
+        let ttw = match settings::var("DRAUGUR_REDISQ_TTW") {
+            Ok(value) => value
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|ttw| (1..=10).contains(ttw))
+                .ok_or_else(|| {
+                    format!(
+                        "DRAUGUR_REDISQ_TTW must be a number of seconds between 1 and 10, got '{}'",
+                        value
+                    )
+                })?,
+            Err(_) => DEFAULT_TTW,
+        };
+
+        Ok(RedisQSettings { queue_id, ttw })
+    }
+}
+
+fn validate_queue_id(queue_id: &str) -> Result<(), String> {
+    if queue_id.is_empty() {
+        return Err("DRAUGUR_QUEUE_ID cannot be empty".to_string());
+    }
+
+    if queue_id.len() > 64 {
+        return Err("DRAUGUR_QUEUE_ID must be at most 64 characters".to_string());
+    }
+
+    if !queue_id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err("DRAUGUR_QUEUE_ID may only contain letters, numbers, '-' and '_'".to_string());
+    }
+
+    Ok(())
+}
+
+/// Long-polls zKillboard's RedisQ service.
+pub struct RedisQSource {
+    client: reqwest::Client,
+    settings: RedisQSettings,
+    url: String,
+}
+
+impl RedisQSource {
+    pub fn new(settings: RedisQSettings) -> RedisQSource {
+        let url = format!(
+            "{}?queueID={}&ttw={}",
+            REDISQ_URL, settings.queue_id, settings.ttw
+        );
+        RedisQSource {
+            client: reqwest::Client::new(),
+            settings,
+            url,
+        }
+    }
+}
+
+#[async_trait]
+impl KillSource for RedisQSource {
+    fn name(&self) -> &'static str {
+        "redisq"
+    }
+
+    async fn run(&mut self, tx: mpsc::Sender<(Killmail, Zkb)>) {
+        // A poll that is already running completes, as RedisQ has handed its
+        // killmail to this queue.
+        while !is_shutting_down() {
+            let timer = REDISQ_POLL_DURATION.start_timer();
+            let response = self.client.get(&self.url).send().await;
+            timer.observe_duration();
+
+            match response {
+                Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    warn!(
+                        "RedisQ rejected a poll on queue '{}' as too many requests. \
+                         Another process appears to be sharing this queue ID",
+                        self.settings.queue_id
+                    );
+                    tokio::time::sleep(Duration::from_secs(self.settings.ttw)).await;
+                }
+                Ok(response) => {
+                    if let Ok(text) = response.text().await
+                        && let Some(kill) = parse_package(&text)
+                        && tx.send(kill).await.is_err()
+                    {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    error!("request failed: {}", e);
+                    tokio::time::sleep(Duration::from_secs(3)).await;
+                }
+            }
+        }
+    }
+}
+
+fn parse_package(text: &str) -> Option<(Killmail, Zkb)> {
+    let redis_response = serde_json::from_str::<serde_json::Value>(text).ok()?;
+    let package = redis_response.get("package")?;
+    if package.is_null() {
+        return None;
+    }
+
+    parse_package_value(package)
+}
@@ -0,0 +1,190 @@
+use chrono::NaiveDateTime;
+use serenity::async_trait;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use super::{KillSource, parse_package_value};
+use crate::models::{Killmail, Zkb};
+use crate::settings;
+use crate::shutdown::is_shutting_down;
+
+/// Longest pause between two replayed killmails, so quiet stretches in a
+/// recording don't stall the replay.
+const MAX_REPLAY_GAP: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+pub struct ReplaySettings {
+    pub path: PathBuf,
+    pub speed: f64,
+}
+
+impl ReplaySettings {
+    /// Reads the replay settings from the environment. Replay is only enabled
+    /// when `DRAUGUR_REPLAY` points at a file or directory.
+    pub fn from_env() -> Option<ReplaySettings> {
+        let path = settings::var("DRAUGUR_REPLAY")
+            .ok()
+            .filter(|p| !p.is_empty())?;
+
+        let speed = match settings::var("DRAUGUR_REPLAY_SPEED") {
+            Ok(value) => match value.parse::<f64>() {
+                Ok(speed) if speed >= 0.0 => speed,
+                _ => {
+                    warn!("invalid DRAUGUR_REPLAY_SPEED '{}', using 1.0", value);
+                    1.0
+                }
+            },
+            Err(_) => 1.0,
+        };
+
+        Some(ReplaySettings {
+            path: settings::data_path(path),
+            speed,
+        })
+    }
+}
+
+/// Feeds recorded killmails through the same pipeline as the live feed.
+///
+/// `speed` scales the gaps between `killmail_time`s: `1.0` replays in real
+/// time, `10.0` ten times faster and `0` as fast as possible.
+pub struct ReplaySource {
+    settings: ReplaySettings,
+}
+
+impl ReplaySource {
+    pub fn new(settings: ReplaySettings) -> ReplaySource {
+        ReplaySource { settings }
+    }
+}
+
+#[async_trait]
+impl KillSource for ReplaySource {
+    fn name(&self) -> &'static str {
+        "replay"
+    }
+
+    fn restarts(&self) -> bool {
+        false
+    }
+
+    async fn run(&mut self, tx: mpsc::Sender<(Killmail, Zkb)>) {
+        let settings = &self.settings;
+        let files = match collect_files(&settings.path).await {
+            Ok(files) => files,
+            Err(e) => {
+                error!("cannot read replay source {:?}: {}", settings.path, e);
+                return;
+            }
+        };
+
+        info!(
+            "replaying {} file(s) from {:?} at {}x",
+            files.len(),
+            settings.path,
+            settings.speed
+        );
+
+        let mut previous_time: Option<NaiveDateTime> = None;
+        let mut replayed = 0;
+
+        for file in files {
+            let kills = match read_packages(&file).await {
+                Ok(kills) => kills,
+                Err(e) => {
+                    error!("cannot read replay file {:?}: {}", file, e);
+                    continue;
+                }
+            };
+
+            for (km, zkb) in kills {
+                if is_shutting_down() {
+                    info!("replay stopped, {} killmail(s) replayed", replayed);
+                    return;
+                }
+
+                let time =
+                    NaiveDateTime::parse_from_str(&km.killmail_time, "%Y-%m-%dT%H:%M:%SZ").ok();
+
+                if settings.speed > 0.0
+                    && let (Some(previous), Some(current)) = (previous_time, time)
+                    && let Ok(gap) = (current - previous).to_std()
+                {
+                    let delay = gap.div_f64(settings.speed).min(MAX_REPLAY_GAP);
+                    tokio::time::sleep(delay).await;
+                }
+                if time.is_some() {
+                    previous_time = time;
+                }
+
+                if tx.send((km, zkb)).await.is_err() {
+                    return;
+                }
+                replayed += 1;
+            }
+        }
+
+        info!("replay finished, {} killmail(s) replayed", replayed);
+    }
+}
+
+async fn collect_files(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if !fs::metadata(path).await?.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file = entry.path();
+        let is_recording = file
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext == "json" || ext == "jsonl");
+        if is_recording {
+            files.push(file);
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+/// Reads the killmails recorded in a file. Both JSON Lines and plain JSON
+/// (a single object or an array) are accepted, and each entry may be a full
+/// RedisQ response (`{"package": ...}`) or the package itself.
+async fn read_packages(path: &Path) -> Result<Vec<(Killmail, Zkb)>, Box<dyn std::error::Error>> {
+    let data = fs::read_to_string(path).await?;
+
+    let values: Vec<serde_json::Value> = match serde_json::from_str(&data) {
+        Ok(serde_json::Value::Array(values)) => values,
+        Ok(value) => vec![value],
+        Err(_) => data
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    warn!("skipping malformed line in {:?}: {}", path, e);
+                    None
+                }
+            })
+            .collect(),
+    };
+
+    let kills = values
+        .iter()
+        .filter_map(|value| {
+            let package = value.get("package").unwrap_or(value);
+            if package.is_null() {
+                return None;
+            }
+            parse_package_value(package)
+        })
+        .collect();
+
+    Ok(kills)
+}
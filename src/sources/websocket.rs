@@ -0,0 +1,115 @@
+use futures::{SinkExt, StreamExt};
+use serenity::async_trait;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, error, info, warn};
+
+use super::KillSource;
+use crate::models::{Killmail, Zkb};
+use crate::settings;
+use crate::shutdown::shutdown_requested;
+
+const WEBSOCKET_URL: &str = "wss://zkillboard.com/websocket/";
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Streams killmails from the zKillboard websocket.
+pub struct WebsocketSource {
+    channels: Vec<String>,
+}
+
+impl WebsocketSource {
+    pub fn new(channels: Vec<String>) -> WebsocketSource {
+        WebsocketSource { channels }
+    }
+
+    /// Subscribes to the channels listed in `DRAUGUR_WS_CHANNELS` (comma
+    /// separated, e.g. `alliance:99000006,system:30000142`), or to the full
+    /// `killstream` when unset.
+    pub fn from_env() -> WebsocketSource {
+        let channels: Vec<String> = settings::var("DRAUGUR_WS_CHANNELS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|channel| channel.trim().to_string())
+            .filter(|channel| !channel.is_empty())
+            .collect();
+
+        if channels.is_empty() {
+            WebsocketSource::new(vec!["killstream".to_string()])
+        } else {
+            WebsocketSource::new(channels)
+        }
+    }
+
+    /// Runs a single connection until it drops. Returns `false` once the
+    /// receiving side of `tx` is gone and the source should stop.
+    async fn listen(
+        &self,
+        tx: &mpsc::Sender<(Killmail, Zkb)>,
+    ) -> Result<bool, tokio_tungstenite::tungstenite::Error> {
+        let (mut stream, _) = connect_async(WEBSOCKET_URL).await?;
+
+        for channel in &self.channels {
+            let subscribe = serde_json::json!({ "action": "sub", "channel": channel });
+            stream.send(Message::Text(subscribe.to_string())).await?;
+        }
+        info!("subscribed to zkillboard websocket: {:?}", self.channels);
+
+        while let Some(message) = stream.next().await {
+            let text = match message? {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+
+            if let Some(kill) = parse_message(&text)
+                && tx.send(kill).await.is_err()
+            {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl KillSource for WebsocketSource {
+    fn name(&self) -> &'static str {
+        "websocket"
+    }
+
+    async fn run(&mut self, tx: mpsc::Sender<(Killmail, Zkb)>) {
+        let mut backoff = Duration::from_secs(1);
+        loop {
+            let result = tokio::select! {
+                result = self.listen(&tx) => result,
+                _ = shutdown_requested() => return,
+            };
+            match result {
+                Ok(false) => return,
+                Ok(true) => {
+                    warn!("zkillboard websocket closed, reconnecting");
+                    backoff = Duration::from_secs(1);
+                }
+                Err(e) => {
+                    error!("zkillboard websocket error: {}", e);
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+            tokio::time::sleep(backoff).await;
+        }
+    }
+}
+
+/// The websocket sends the ESI killmail with the zkb block embedded in it.
+fn parse_message(text: &str) -> Option<(Killmail, Zkb)> {
+    let value = serde_json::from_str::<serde_json::Value>(text).ok()?;
+    let zkb_data = value.get("zkb")?;
+    debug!("ZKB DATA: {:?}", zkb_data);
+
+    let parsed = serde_json::from_value::<Killmail>(value.clone()).ok()?;
+    let zkb = serde_json::from_value::<Zkb>(zkb_data.clone()).ok()?;
+    Some((parsed, zkb))
+}
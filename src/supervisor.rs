@@ -0,0 +1,62 @@
+use std::any::Any;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+use crate::metrics::TASK_RESTARTS;
+use crate::shutdown::{is_shutting_down, shutdown_requested};
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Runs the task built by `start` and starts it again whenever it panics or
+/// returns, waiting longer after each failure in a row. A task that ran for
+/// longer than the maximum backoff starts over with the minimum. Nothing is
+/// restarted once shutdown has started.
+pub async fn supervise<F, Fut>(name: &'static str, mut start: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut backoff = MIN_BACKOFF;
+
+    loop {
+        let started = Instant::now();
+        let result = tokio::spawn(start()).await;
+
+        if is_shutting_down() {
+            return;
+        }
+
+        match result {
+            Ok(()) => warn!("{} task exited", name),
+            Err(e) if e.is_panic() => {
+                error!("{} task panicked: {}", name, panic_message(e.into_panic()))
+            }
+            Err(e) => error!("{} task failed: {}", name, e),
+        }
+
+        if started.elapsed() > MAX_BACKOFF {
+            backoff = MIN_BACKOFF;
+        }
+        info!("restarting {} task in {:?}", name, backoff);
+
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = shutdown_requested() => return,
+        }
+        TASK_RESTARTS.with_label_values(&[name]).inc();
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// The message a panic was raised with, if it had one.
+pub fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown cause".to_string()
+    }
+}
@@ -1,135 +1,354 @@
-use crate::helpers::{get_vic_info, track_recent_kill};
+use crate::archive::archive_kill;
+use crate::dedup::is_new_kill;
+use crate::enrich::EnrichedKill;
+use crate::health::{feed_started, kill_received};
+use crate::helpers::track_recent_kill;
+use crate::metrics::{
+    FEED_LAG, KILLMAIL_TASK_PANICS, KILLMAILS_DUPLICATE, KILLMAILS_MATCHED, KILLMAILS_RECEIVED,
+};
 use crate::models::Killmail;
 use crate::models::Zkb;
-use crate::msg::create_msg;
+use crate::msg::send_msg;
+use crate::shutdown::{set_feed_running, shutdown_requested};
+use crate::sources::KillSource;
+use crate::supervisor::panic_message;
 
-use log::{debug, error};
+use chrono::{NaiveDateTime, Utc};
+use futures::FutureExt;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use serenity::client::Context;
 use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, mpsc};
+use tokio::task::{AbortHandle, JoinSet};
+use tracing::{Instrument, debug, error, info, info_span};
 
+const KILL_QUEUE_SIZE: usize = 256;
+/// How many killmails are enriched and dispatched at once. Past this the feed
+/// waits for one to finish before taking the next.
+const MAX_CONCURRENT_KILLS: usize = 16;
+/// How long a kill source gets to return on its own during shutdown, long
+/// enough for a RedisQ poll to finish.
+const SOURCE_GRACE: Duration = Duration::from_secs(15);
 pub static SERVER_CONFIGS: Lazy<RwLock<HashMap<u64, ServerConfig>>> =
     Lazy::new(|| RwLock::new(HashMap::new()));
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub follow_ids: Vec<i64>,
     pub channel_id: u64,
+    /// Why posting was turned off, e.g. because the channel was deleted.
+    /// Running `/setup` again clears it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disabled: Option<String>,
+    /// Role whose members may change this configuration without having
+    /// Manage Server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_role_id: Option<u64>,
 }
 
-pub async fn kill_feed(ctx: &Context) {
-    let url = format!("https://zkillredisq.stream/listen.php?queueID=draugur");
-
-    let client = reqwest::Client::new();
-    loop {
-        match client.get(&url).send().await {
-            Ok(response) => {
-                if let Ok(text) = response.text().await {
-                    if let Ok(redis_response) = serde_json::from_str::<serde_json::Value>(&text) {
-                        if let Some(package) = redis_response.get("package") {
-                            if package.is_null() {
-                                continue;
-                            }
-
-                            if let Some(killmail_data) = package.get("killmail") {
-                                if let Some(zkb_data) = package.get("zkb") {
-                                    debug!("ZKB DATA: {:?}", zkb_data);
-                                    if let Ok(parsed) =
-                                        serde_json::from_value::<Killmail>(killmail_data.clone())
-                                    {
-                                        if let Ok(zkb) =
-                                            serde_json::from_value::<Zkb>(zkb_data.clone())
-                                        {
-                                            let (vic, vic_ship) =
-                                                get_vic_info(parsed.clone()).await;
-
-                                            track_recent_kill(
-                                                parsed.killmail_id,
-                                                zkb.total_value,
-                                                vic,
-                                                vic_ship,
-                                            )
-                                            .await;
-                                            let mut a_ids: Vec<i64> = Vec::new();
-                                            for attacker in parsed.attackers.iter() {
-                                                if let Some(alliance_id) = attacker.alliance_id {
-                                                    a_ids.push(alliance_id);
-                                                }
-                                            }
-                                            let mut corp_ids: Vec<i64> = Vec::new();
-                                            for attacker in parsed.attackers.iter() {
-                                                corp_ids.push(attacker.corporation_id);
-                                            }
-                                            let configs = SERVER_CONFIGS.read().await;
-                                            for (_guild_id, config) in configs.iter() {
-                                                if let Some(kill_type) =
-                                                    should_track(&parsed, &config.follow_ids[..])
-                                                {
-                                                    create_msg(
-                                                        ctx,
-                                                        config.channel_id,
-                                                        kill_type,
-                                                        parsed.clone(),
-                                                        zkb.clone(),
-                                                    )
-                                                    .await;
-                                                }
-                                            }
-                                            drop(configs);
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+/// Marks the feed as stopped and stops its source when `kill_feed` ends,
+/// including when it panics.
+struct FeedGuard {
+    source: AbortHandle,
+}
+
+impl Drop for FeedGuard {
+    fn drop(&mut self) {
+        self.source.abort();
+        set_feed_running(false);
+    }
+}
+
+/// Runs the configured kill source and processes everything it delivers.
+/// A panic in the source is passed on once the queued killmails are handled;
+/// one while handling a killmail only skips that killmail.
+pub async fn kill_feed(ctx: &Context, mut source: Box<dyn KillSource>) {
+    info!("starting {} kill source", source.name());
+    set_feed_running(true);
+    feed_started();
+
+    let (tx, mut rx) = mpsc::channel::<(Killmail, Zkb)>(KILL_QUEUE_SIZE);
+    let source_task = tokio::spawn(async move {
+        source.run(tx).await;
+        info!("{} kill source finished", source.name());
+    });
+    let _guard = FeedGuard {
+        source: source_task.abort_handle(),
+    };
+
+    // Sources stop fetching once shutdown starts. One that doesn't return in
+    // time is stopped, which also ends the loop below.
+    let source_abort = source_task.abort_handle();
+    tokio::spawn(async move {
+        shutdown_requested().await;
+        tokio::time::sleep(SOURCE_GRACE).await;
+        source_abort.abort();
+    });
+
+    // Enrichment waits on ESI, so each new killmail is handled in its own
+    // task and a slow lookup only holds up that one kill.
+    let mut in_flight = JoinSet::new();
+    while let Some((parsed, zkb)) = rx.recv().await {
+        let span = info_span!(
+            "killmail",
+            id = parsed.killmail_id,
+            system = parsed.solar_system_id,
+            value = zkb.total_value
+        );
+
+        KILLMAILS_RECEIVED.inc();
+        kill_received();
+        if !is_new_kill(parsed.killmail_id)
+            .instrument(span.clone())
+            .await
+        {
+            KILLMAILS_DUPLICATE.inc();
+            continue;
+        }
+
+        while in_flight.try_join_next().is_some() {}
+        if in_flight.len() >= MAX_CONCURRENT_KILLS {
+            in_flight.join_next().await;
+        }
+
+        // A panic is caught per killmail, so one bad kill doesn't take the
+        // others in flight and the queued ones down with the feed.
+        let ctx = ctx.clone();
+        in_flight.spawn(
+            async move {
+                let handled = AssertUnwindSafe(handle_kill(&ctx, parsed, zkb))
+                    .catch_unwind()
+                    .await;
+                if let Err(payload) = handled {
+                    KILLMAIL_TASK_PANICS.inc();
+                    error!("handling killmail panicked: {}", panic_message(payload));
                 }
             }
-            Err(e) => {
-                error!("request failed: {}", e);
-                tokio::time::sleep(Duration::from_secs(3)).await;
-            }
-        }
+            .instrument(span),
+        );
     }
-}
 
-fn should_track(km: &Killmail, follow_ids: &[i64]) -> Option<String> {
-    if follow_ids.contains(&km.victim.character_id)
-        || follow_ids.contains(&km.victim.corporation_id)
-        || km
-            .victim
-            .alliance_id
-            .map_or(false, |id| follow_ids.contains(&id))
+    while in_flight.join_next().await.is_some() {}
+
+    if let Err(e) = source_task.await
+        && e.is_panic()
     {
-        return Some("loss".to_string());
+        std::panic::resume_unwind(e.into_panic());
+    }
+}
+
+/// Enriches a new killmail and posts it to every guild that follows it.
+async fn handle_kill(ctx: &Context, parsed: Killmail, zkb: Zkb) {
+    if let Ok(time) = NaiveDateTime::parse_from_str(&parsed.killmail_time, "%Y-%m-%dT%H:%M:%SZ") {
+        let lag = Utc::now().naive_utc() - time;
+        FEED_LAG.observe(lag.num_milliseconds().max(0) as f64 / 1000.0);
+    }
+
+    let kill = Arc::new(EnrichedKill::new(parsed, zkb).await);
+
+    track_recent_kill(Arc::clone(&kill)).await;
+
+    let matches: Vec<(u64, u64, String)> = {
+        let configs = SERVER_CONFIGS.read().await;
+        configs
+            .iter()
+            .filter(|(_, config)| config.disabled.is_none())
+            .filter_map(|(guild_id, config)| {
+                should_track(&kill.killmail, &config.follow_ids[..])
+                    .map(|kill_type| (*guild_id, config.channel_id, kill_type))
+            })
+            .collect()
+    };
+    let tracked = !matches.is_empty();
+    if tracked {
+        KILLMAILS_MATCHED.inc();
+    }
+    debug!("matched {} server(s)", matches.len());
+
+    for (guild_id, channel_id, kill_type) in matches {
+        let _span = info_span!(
+            "delivery",
+            guild = guild_id,
+            channel = channel_id,
+            kill_type = %kill_type
+        )
+        .entered();
+        send_msg(ctx, channel_id, &kill, &kill_type);
+    }
+
+    archive_kill(&kill, tracked);
+}
+
+/// Why a killmail matched a guild's follows.
+#[derive(Clone, Debug)]
+pub struct TrackMatch {
+    pub kill_type: String,
+    pub follow_id: i64,
+    pub field: &'static str,
+}
+
+pub fn should_track(km: &Killmail, follow_ids: &[i64]) -> Option<String> {
+    find_match(km, follow_ids).map(|found| found.kill_type)
+}
+
+/// Finds the first follow that matches the killmail. Victims are checked
+/// before attackers, and people before systems and ship types.
+pub fn find_match(km: &Killmail, follow_ids: &[i64]) -> Option<TrackMatch> {
+    let found = |kill_type: &str, follow_id: i64, field: &'static str| {
+        Some(TrackMatch {
+            kill_type: kill_type.to_string(),
+            follow_id,
+            field,
+        })
+    };
+
+    let victim_ids = [
+        (km.victim.character_id, "victim character"),
+        (km.victim.corporation_id, "victim corporation"),
+        (km.victim.alliance_id.unwrap_or(0), "victim alliance"),
+    ];
+    for (id, field) in victim_ids {
+        if id != 0 && follow_ids.contains(&id) {
+            return found("loss", id, field);
+        }
     }
 
     for attacker in &km.attackers {
-        if follow_ids.contains(&attacker.character_id)
-            || follow_ids.contains(&attacker.corporation_id)
-            || attacker
-                .alliance_id
-                .map_or(false, |id| follow_ids.contains(&id))
-        {
-            return Some("kill".to_string());
+        let attacker_ids = [
+            (attacker.character_id, "attacker character"),
+            (attacker.corporation_id, "attacker corporation"),
+            (attacker.alliance_id.unwrap_or(0), "attacker alliance"),
+        ];
+        for (id, field) in attacker_ids {
+            if id != 0 && follow_ids.contains(&id) {
+                return found("kill", id, field);
+            }
         }
     }
 
     if follow_ids.contains(&km.solar_system_id) {
-        return Some("kill".to_string());
+        return found("kill", km.solar_system_id, "solar system");
     }
 
     if follow_ids.contains(&km.victim.ship_type_id) {
-        return Some("loss".to_string());
+        return found("loss", km.victim.ship_type_id, "victim ship type");
     }
 
     for attacker in &km.attackers {
         if follow_ids.contains(&attacker.ship_type_id) {
-            return Some("kill".to_string());
+            return found("kill", attacker.ship_type_id, "attacker ship type");
         }
     }
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Attacker, Victim};
+
+    fn killmail() -> Killmail {
+        Killmail {
+            attackers: vec![
+                Attacker {
+                    character_id: 2001,
+                    corporation_id: 3001,
+                    ship_type_id: 17738,
+                    ..Default::default()
+                },
+                Attacker {
+                    character_id: 0,
+                    corporation_id: 0,
+                    alliance_id: Some(4002),
+                    ship_type_id: 587,
+                    ..Default::default()
+                },
+            ],
+            solar_system_id: 30000142,
+            victim: Victim {
+                character_id: 1001,
+                corporation_id: 1002,
+                alliance_id: Some(1003),
+                ship_type_id: 670,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn matched(follow_ids: &[i64]) -> Option<(String, i64, &'static str)> {
+        find_match(&killmail(), follow_ids)
+            .map(|found| (found.kill_type, found.follow_id, found.field))
+    }
+
+    #[test]
+    fn victim_fields_are_losses() {
+        assert_eq!(
+            matched(&[1001]),
+            Some(("loss".into(), 1001, "victim character"))
+        );
+        assert_eq!(
+            matched(&[1002]),
+            Some(("loss".into(), 1002, "victim corporation"))
+        );
+        assert_eq!(
+            matched(&[1003]),
+            Some(("loss".into(), 1003, "victim alliance"))
+        );
+        assert_eq!(
+            matched(&[670]),
+            Some(("loss".into(), 670, "victim ship type"))
+        );
+    }
+
+    #[test]
+    fn attacker_fields_are_kills() {
+        assert_eq!(
+            matched(&[2001]),
+            Some(("kill".into(), 2001, "attacker character"))
+        );
+        assert_eq!(
+            matched(&[3001]),
+            Some(("kill".into(), 3001, "attacker corporation"))
+        );
+        assert_eq!(
+            matched(&[4002]),
+            Some(("kill".into(), 4002, "attacker alliance"))
+        );
+        assert_eq!(
+            matched(&[587]),
+            Some(("kill".into(), 587, "attacker ship type"))
+        );
+        assert_eq!(
+            matched(&[30000142]),
+            Some(("kill".into(), 30000142, "solar system"))
+        );
+    }
+
+    #[test]
+    fn victims_are_checked_before_attackers_and_people_before_ships() {
+        assert_eq!(
+            matched(&[2001, 1002]),
+            Some(("loss".into(), 1002, "victim corporation"))
+        );
+        assert_eq!(
+            matched(&[670, 2001]),
+            Some(("kill".into(), 2001, "attacker character"))
+        );
+        assert_eq!(
+            matched(&[587, 30000142]),
+            Some(("kill".into(), 30000142, "solar system"))
+        );
+    }
+
+    #[test]
+    fn missing_ids_never_match() {
+        assert_eq!(matched(&[0]), None);
+        assert_eq!(matched(&[9999]), None);
+    }
+}
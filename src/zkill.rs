@@ -0,0 +1,111 @@
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+use crate::models::{Killmail, Zkb};
+
+const ZKILL_API: &str = "https://zkillboard.com/api/killID/";
+const ESI_KILLMAILS: &str = "https://esi.evetech.net/latest/killmails/";
+
+// zKillboard rejects requests without a descriptive user agent.
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .user_agent(concat!(
+            "draugur/",
+            env!("CARGO_PKG_VERSION"),
+            " (+https://github.com/itsTykho/draugur)"
+        ))
+        .build()
+        .unwrap_or_default()
+});
+
+#[derive(Deserialize)]
+struct ZkillEntry {
+    killmail_id: i64,
+    zkb: Zkb,
+}
+
+/// Fetches a single killmail on demand: zKillboard for the hash and value,
+/// then ESI for the killmail itself.
+pub async fn fetch_kill(killmail_id: i64) -> Result<(Killmail, Zkb), String> {
+    let url = format!("{}{}/", ZKILL_API, killmail_id);
+    let entries: Vec<ZkillEntry> = CLIENT
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("zKillboard lookup failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("unexpected zKillboard response: {}", e))?;
+
+    let mut zkb = match entries
+        .into_iter()
+        .find(|entry| entry.killmail_id == killmail_id)
+    {
+        Some(entry) => entry.zkb,
+        None => return Err(format!("zKillboard doesn't know killmail {}", killmail_id)),
+    };
+    zkb.killmail_id = killmail_id;
+
+    let url = format!(
+        "{}{}/{}/?datasource=tranquility",
+        ESI_KILLMAILS, killmail_id, zkb.hash
+    );
+    let killmail: Killmail = CLIENT
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("ESI lookup failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("unexpected ESI response: {}", e))?;
+
+    Ok((killmail, zkb))
+}
+
+/// Reads a killmail ID from either a bare number or a zKillboard kill URL.
+pub fn parse_kill_id(input: &str) -> Option<i64> {
+    let input = input.trim().trim_end_matches('/');
+    let id = match input.rfind("/kill/") {
+        Some(start) => &input[start + "/kill/".len()..],
+        None => input,
+    };
+    id.parse::<i64>().ok().filter(|id| *id > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bare_id_is_parsed() {
+        assert_eq!(parse_kill_id("128374619"), Some(128374619));
+        assert_eq!(parse_kill_id("  128374619 "), Some(128374619));
+    }
+
+    #[test]
+    fn kill_url_is_parsed() {
+        assert_eq!(
+            parse_kill_id("https://zkillboard.com/kill/128374619/"),
+            Some(128374619)
+        );
+        assert_eq!(
+            parse_kill_id("https://zkillboard.com/kill/128374619"),
+            Some(128374619)
+        );
+    }
+
+    #[test]
+    fn junk_is_rejected() {
+        assert_eq!(parse_kill_id(""), None);
+        assert_eq!(parse_kill_id("abc"), None);
+        assert_eq!(parse_kill_id("0"), None);
+        assert_eq!(parse_kill_id("-5"), None);
+        assert_eq!(parse_kill_id("https://zkillboard.com/kill/"), None);
+        assert_eq!(
+            parse_kill_id("https://zkillboard.com/character/2113678525/"),
+            None
+        );
+    }
+}